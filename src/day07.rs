@@ -3,6 +3,9 @@ use std::fmt;
 use std::time::Instant;
 use std::collections::VecDeque;
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
 #[derive(Clone)]
 struct Test {
     result: i64,
@@ -156,32 +159,35 @@ fn main() {
     let tests = parse_input("data/input_07.txt");
 
     let before = Instant::now();
-    let mut total = 0;
-    let mut result = 0;
-    for test in tests.iter() {
-        if test.check_pt1() {
-            total += 1;
-            result += test.result;
-        }
-    }
+    let (total, result) = calibrate(&tests, Test::check_pt1);
     println!("Time: {:.2?}, Passed: {:?}/{:?}, Result: {:?}", before.elapsed(), total, tests.len(), result);
     
     let before = Instant::now();
-    let mut total = 0;
-    let mut result = 0;
-    for test in tests.iter() {
-        if test.check_pt2() {
-            total += 1;
-            result += test.result;
-        }
-    }
+    let (total, result) = calibrate(&tests, Test::check_pt2);
     println!("Time: {:.2?}, Passed: {:?}/{:?}, Result: {:?}", before.elapsed(), total, tests.len(), result);
 }
 
+// Returns the number of passing equations and the sum of their results. Each
+// equation is independent, so with the `parallel` feature they are checked
+// across all cores; the totals are integer sums and match the serial path.
+#[cfg(not(feature = "parallel"))]
+fn calibrate(tests: &[Test], check: fn(&Test) -> bool) -> (usize, i64) {
+    tests.iter()
+        .filter(|t| check(t))
+        .fold((0, 0), |(total, result), t| (total + 1, result + t.result))
+}
+
+#[cfg(feature = "parallel")]
+fn calibrate(tests: &[Test], check: fn(&Test) -> bool) -> (usize, i64) {
+    tests.par_iter()
+        .filter(|t| check(t))
+        .map(|t| (1, t.result))
+        .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1))
+}
+
 fn parse_input(fname: &str) -> Vec<Test> {
     let mut tests: Vec<Test> = Vec::<Test>::new();
     for line in read_to_string(fname).unwrap().lines() {
-        println!("{:?}", line);
         let fields: Vec<&str> = line.split(": ").collect();
         let result: i64 = fields[0].parse::<i64>().unwrap();
         let operands: Vec<i64> = fields[1].split(" ").map(|c| c.parse::<i64>().unwrap()).collect();
@@ -1,57 +1,46 @@
 use std::fs::read_to_string;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use colored::{Color, Colorize};
 
-fn main() {
-    let (antennas, dim) = parse_input("data/input_08.txt");
+type Pos = (i32, i32);
 
-    let mut map: Vec<i32> = vec![0; (dim.0 * dim.1).try_into().unwrap()];
-    for (_key, val) in antennas.iter() {
-        for i in 0..val.len() {
-            for j in (i+1)..val.len() {
-                let an = calc_antinode(val[i], val[j]);
-                if in_bounds(an, dim) {
-                    map[(an.0 * dim.1 + an.1) as usize] = 1;
-                }
+// Antinodes grouped by frequency, each mapped to the antenna pairs that produce it.
+type Antinodes = HashMap<char, HashMap<Pos, Vec<(Pos, Pos)>>>;
 
-                let an = calc_antinode(val[j], val[i]);
-                if in_bounds(an, dim) {
-                    map[(an.0 * dim.1 + an.1) as usize] = 1;
-                }
-            }
-        }
-    }
+const PALETTE: [Color; 6] = [Color::Red, Color::Green, Color::Yellow, Color::Blue, Color::Magenta, Color::Cyan];
 
-    let mut total: i32 = 0;
-    for i in 0..map.len() {
-        total += map[i];
-    }
-    println!("Part 1: {:?}", total);
-   
-    let mut map: Vec<i32> = vec![0; (dim.0 * dim.1).try_into().unwrap()];
-    for (_key, val) in antennas.iter() {
-        for i in 0..val.len() {
-            for j in (i+1)..val.len() {
-                let ans = calc_antinodes(val[i], val[j], dim);
-                for an in ans {
-                    map[(an.0 * dim.1 + an.1) as usize] = 1;
-                }
+// Describes which points on the line through a pair of antennas count as
+// antinodes. Harmonic `n` is the point `n` steps beyond an antenna, heading
+// away from its partner, so part 1 is `1..2` and part 2 is `0..i32::MAX`. With
+// `reduce` the step is the pair delta divided by its gcd, and with `between`
+// the lattice points strictly between the two antennas are included as well.
+struct AntinodeRule {
+    harmonics: Range<i32>,
+    reduce: bool,
+    between: bool,
+}
 
-                let ans = calc_antinodes(val[j], val[i], dim);
-                for an in ans {
-                    map[(an.0 * dim.1 + an.1) as usize] = 1;
-                }
+// Usage: day08 [--report]
+fn main() {
+    let report = std::env::args().skip(1).any(|a| a == "--report");
+    let (antennas, dim) = parse_input("data/input_08.txt");
 
-                map[(val[i].0 * dim.1 + val[i].1) as usize] = 1;
-                map[(val[j].0 * dim.1 + val[j].1) as usize] = 1;
-            }
-        }
+    let rule = AntinodeRule{harmonics: 1..2, reduce: false, between: false};
+    let antinodes = find_antinodes(&antennas, dim, &rule);
+    if report {
+        print_report(&antinodes);
+        print_overlay(&antennas, &antinodes, dim);
     }
-
-    let mut total: i32 = 0;
-    for i in 0..map.len() {
-        total += map[i];
+    println!("Part 1: {:?}", count_unique(&antinodes));
+   
+    let rule = AntinodeRule{harmonics: 0..i32::MAX, reduce: true, between: true};
+    let antinodes = find_antinodes(&antennas, dim, &rule);
+    if report {
+        print_report(&antinodes);
+        print_overlay(&antennas, &antinodes, dim);
     }
-    println!("Part 2: {:?}", total);
+    println!("Part 2: {:?}", count_unique(&antinodes));
 }
 
 fn parse_input(fname: &str) -> (HashMap<char, Vec<(i32, i32)>>, (i32, i32)) {
@@ -80,22 +69,140 @@ fn parse_input(fname: &str) -> (HashMap<char, Vec<(i32, i32)>>, (i32, i32)) {
     return (antennas, dim)
 }
 
-fn calc_antinode(n1: (i32, i32), n2: (i32, i32)) -> (i32, i32) {
-    (2 * n2.0 - n1.0, 2 * n2.1 - n1.1)
+fn find_antinodes(antennas: &HashMap<char, Vec<Pos>>, dim: Pos, rule: &AntinodeRule) -> Antinodes {
+    let mut antinodes: Antinodes = HashMap::new();
+    for (key, val) in antennas.iter() {
+        let nodes = antinodes.entry(*key).or_default();
+        for i in 0..val.len() {
+            for j in (i+1)..val.len() {
+                for an in calc_antinodes(val[i], val[j], dim, rule) {
+                    let pairs = nodes.entry(an).or_default();
+                    if !pairs.contains(&(val[i], val[j])) {
+                        pairs.push((val[i], val[j]));
+                    }
+                }
+            }
+        }
+    }
+
+    antinodes
+}
+
+fn count_unique(antinodes: &Antinodes) -> usize {
+    antinodes.values().flat_map(|nodes| nodes.keys()).collect::<HashSet<_>>().len()
 }
 
-fn calc_antinodes(n1: (i32, i32), n2: (i32, i32), dim: (i32, i32)) -> Vec<(i32, i32)> {
-    let mut ans: Vec<(i32, i32)> = Vec::<(i32, i32)>::new();
-    let mut i: i32 = 1;
+fn sorted_keys<K: Ord + Copy, V>(m: &HashMap<K, V>) -> Vec<K> {
+    let mut keys: Vec<K> = m.keys().copied().collect();
+    keys.sort();
+    keys
+}
 
-    while in_bounds(((i + 1) * n2.0 - i * n1.0, (i + 1) * n2.1 - i * n1.1), dim) {
-        ans.push(((i + 1) * n2.0 - i * n1.0, (i + 1) * n2.1 - i * n1.1));
-        i += 1;
+fn print_report(antinodes: &Antinodes) {
+    for freq in sorted_keys(antinodes) {
+        let nodes = &antinodes[&freq];
+        println!("Frequency {:?}: {} antinodes", freq, nodes.len());
+        for an in sorted_keys(nodes) {
+            let pairs: Vec<String> = nodes[&an].iter().map(|(a, b)| format!("{:?}-{:?}", a, b)).collect();
+            println!("    {:?} <- {}", an, pairs.join(", "));
+        }
+    }
+}
+
+// Draws the grid with each frequency in its own color. Antennas are shown by
+// their label, antinodes by `#`, and cells claimed by more than one frequency
+// by a white `*`. An antenna that is also an antinode gets a colored background.
+fn print_overlay(antennas: &HashMap<char, Vec<Pos>>, antinodes: &Antinodes, dim: Pos) {
+    let mut colors: HashMap<char, Color> = HashMap::new();
+    for (i, freq) in sorted_keys(antennas).into_iter().enumerate() {
+        colors.insert(freq, PALETTE[i % PALETTE.len()]);
+    }
+
+    let mut labels: HashMap<Pos, char> = HashMap::new();
+    for (freq, val) in antennas.iter() {
+        for p in val {
+            labels.insert(*p, *freq);
+        }
+    }
+
+    let mut owners: HashMap<Pos, Vec<char>> = HashMap::new();
+    for (freq, nodes) in antinodes.iter() {
+        for an in nodes.keys() {
+            owners.entry(*an).or_default().push(*freq);
+        }
     }
 
-    return ans;
+    for i in 0..dim.0 {
+        for j in 0..dim.1 {
+            let cell = match (labels.get(&(i, j)), owners.get(&(i, j))) {
+                (Some(c), None) => c.to_string().color(colors[c]).bold(),
+                (Some(c), Some(o)) => c.to_string().black().on_color(colors[&o[0]]),
+                (None, Some(o)) if o.len() > 1 => "*".white().bold(),
+                (None, Some(o)) => "#".color(colors[&o[0]]),
+                (None, None) => ".".dimmed(),
+            };
+            print!("{}", cell);
+        }
+        println!();
+    }
+    println!();
+}
+
+fn calc_antinodes(n1: Pos, n2: Pos, dim: Pos, rule: &AntinodeRule) -> Vec<Pos> {
+    let mut ans: Vec<Pos> = Vec::<Pos>::new();
+    let full = (n2.0 - n1.0, n2.1 - n1.1);
+    let g = gcd(full.0, full.1);
+    let unit = (full.0 / g, full.1 / g);
+    let delta = if rule.reduce { unit } else { full };
+
+    for (start, dir) in [(n2, 1), (n1, -1)] {
+        for n in rule.harmonics.clone() {
+            // Any overflow is far outside the grid.
+            let an = (dir * n).checked_mul(delta.0).and_then(|d| start.0.checked_add(d))
+                .zip((dir * n).checked_mul(delta.1).and_then(|d| start.1.checked_add(d)));
+            match an {
+                Some(an) if in_bounds(an, dim) => ans.push(an),
+                _ => break,
+            }
+        }
+    }
+
+    if rule.between {
+        for k in 1..g {
+            ans.push((n1.0 + k * unit.0, n1.1 + k * unit.1));
+        }
+    }
+
+    ans
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
 }
 
 fn in_bounds(p: (i32, i32), dim: (i32, i32)) -> bool {
     p.0 >= 0 && p.0 < dim.0 && p.1 >= 0 && p.1 < dim.1
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn between_without_reduce() {
+        let rule = AntinodeRule{harmonics: 1..2, reduce: false, between: true};
+        let mut ans = calc_antinodes((0, 0), (3, 6), (20, 20), &rule);
+        ans.sort();
+        assert_eq!(ans, vec![(1, 2), (2, 4), (6, 12)]);
+    }
+
+    #[test]
+    fn far_harmonics_do_not_overflow() {
+        let rule = AntinodeRule{harmonics: i32::MAX - 2..i32::MAX, reduce: false, between: false};
+        assert!(calc_antinodes((5, 5), (7, 8), (10, 10), &rule).is_empty());
+    }
+}
@@ -1,164 +1,279 @@
 use std::fs::read_to_string;
-use std::cmp;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 
-pub struct List<T> {
-    head: Link<T>,
-}
+use aoc_2024::list::{Handle, List};
 
-type Link<T> = Option<Box<Node<T>>>;
+// A free span as `(start, length)`.
+type Span = (usize, usize);
 
-struct Node<T> {
-    elem: T,
-    next: Link<T>,
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Extent {
+    id: Option<usize>,
+    start: usize,
+    len: usize,
 }
 
-impl<T> List<T> {
-    pub fn new() -> Self {
-        List { head: None }
-    }
+// Disk modelled as an ordered list of file and free extents covering every
+// block. A file starts as a single extent but block-level compaction may split
+// it, so `files` keeps the handles of each file's extents. Free spans are also
+// indexed in one min-heap per span length, keyed by start offset, so the
+// leftmost span of a given length is always at the head of its heap. Entries
+// are dropped lazily once the span they point at is split or merged away.
+#[derive(Clone)]
+struct FileSystem {
+    layout: List<Extent>,
+    files: Vec<Vec<Handle>>,
+    free: Vec<BinaryHeap<Reverse<(usize, Handle)>>>,
+}
 
-    pub fn push(&mut self, elem: T) {
-        let new_node = Box::new(Node {
-            elem: elem,
-            next: self.head.take(),
-        });
+struct CompactionReport {
+    moves: usize,
+    holes: usize,
+    fragmented_files: usize,
+    largest_free: usize,
+    check_sum: i64,
+}
 
-        self.head = Some(new_node);
-    }
+impl FileSystem {
+    fn new(compressed: &[(i32, i32)]) -> Self {
+        let mut fs = FileSystem{layout: List::new(), files: Vec::new(), free: Vec::new()};
+
+        let mut start: usize = 0;
+        for (id, size) in compressed.iter() {
+            let len = *size as usize;
+            if *id > -1 {
+                let h = fs.layout.push_back(Extent{id: Some(*id as usize), start, len});
+                fs.files.push(vec![h]);
+            } else if len > 0 {
+                let h = fs.layout.push_back(Extent{id: None, start, len});
+                fs.index_free(h);
+            }
+            start += len;
+        }
 
-    pub fn pop(&mut self) -> Option<T> {
-        self.head.take().map(|node| {
-            self.head = node.next;
-            node.elem
-        })
+        fs
     }
 
-    pub fn peek(&self) -> Option<&T> {
-        self.head.as_ref().map(|node| {
-            &node.elem
-        })
+    fn index_free(&mut self, h: Handle) {
+        let extent = *self.layout.get(h).unwrap();
+        if self.free.len() <= extent.len {
+            self.free.resize_with(extent.len + 1, Default::default);
+        }
+        self.free[extent.len].push(Reverse((extent.start, h)));
     }
 
-    pub fn peek_mut(&mut self) -> Option<&mut T> {
-        self.head.as_mut().map(|node| {
-            &mut node.elem
-        })
+    // Heads of the free heaps that can hold `len` blocks and start before
+    // `before`, in ascending span length.
+    fn candidates(&mut self, len: usize, before: usize) -> Vec<Span> {
+        let mut spans: Vec<Span> = Vec::<Span>::new();
+        for l in len..self.free.len() {
+            while let Some(Reverse((start, h))) = self.free[l].peek() {
+                if self.layout.get(*h) == Some(&Extent{id: None, start: *start, len: l}) {
+                    if *start < before {
+                        spans.push((*start, l));
+                    }
+                    break;
+                }
+                self.free[l].pop();
+            }
+        }
+
+        spans
     }
 
-    pub fn into_iter(self) -> IntoIter<T> {
-        IntoIter(self)
+    // Places `len` blocks of file `id` at the head of the free heap for
+    // `span`, splitting off any remainder as a smaller free extent.
+    fn allocate(&mut self, span: usize, id: usize, len: usize) -> Handle {
+        let Reverse((start, h)) = self.free[span].pop().unwrap();
+        if span == len {
+            self.layout.get_mut(h).unwrap().id = Some(id);
+            return h;
+        }
+
+        let rest = self.layout.get_mut(h).unwrap();
+        rest.start += len;
+        rest.len -= len;
+        self.index_free(h);
+
+        self.layout.insert_before(h, Extent{id: Some(id), start, len})
     }
 
-    pub fn iter(&self) -> Iter<'_, T> {
-        Iter { next: self.head.as_deref() }
+    // Frees the last `len` blocks of the file extent at `h`, merging the freed
+    // space with any free neighbours. Returns true if the whole extent went.
+    fn release(&mut self, h: Handle, len: usize) -> bool {
+        let extent = self.layout.get_mut(h).unwrap();
+        let whole = extent.len == len;
+        let freed = if whole {
+            extent.id = None;
+            h
+        } else {
+            extent.len -= len;
+            let start = extent.start + extent.len;
+            self.layout.insert_after(h, Extent{id: None, start, len})
+        };
+
+        if let Some(prev) = self.layout.prev(freed).filter(|p| self.layout.get(*p).unwrap().id.is_none()) {
+            let prev = self.layout.remove(prev).unwrap();
+            let extent = self.layout.get_mut(freed).unwrap();
+            extent.start = prev.start;
+            extent.len += prev.len;
+        }
+        if let Some(next) = self.layout.next(freed).filter(|n| self.layout.get(*n).unwrap().id.is_none()) {
+            let next = self.layout.remove(next).unwrap();
+            self.layout.get_mut(freed).unwrap().len += next.len;
+        }
+        self.index_free(freed);
+
+        whole
     }
 
-    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
-        IterMut { next: self.head.as_deref_mut() }
+    fn check_sum(&self) -> i64 {
+        let mut val: i64 = 0;
+        for extent in self.layout.iter() {
+            if let Some(id) = extent.id {
+                let (start, len) = (extent.start as i64, extent.len as i64);
+                val += (id as i64) * (len * start + len * (len - 1) / 2);
+            }
+        }
+
+        val
     }
-}
 
-impl<T> Drop for List<T> {
-    fn drop(&mut self) {
-        let mut cur_link = self.head.take();
-        while let Some(mut boxed_node) = cur_link {
-            cur_link = boxed_node.next.take();
+    fn report(&self, moves: usize) -> CompactionReport {
+        let mut holes = 0;
+        let mut gaps = 0;
+        let mut largest_free = 0;
+        for extent in self.layout.iter() {
+            if extent.id.is_some() {
+                holes += gaps;
+                gaps = 0;
+            } else if extent.len > 0 {
+                gaps += 1;
+                largest_free = largest_free.max(extent.len);
+            }
+        }
+
+        CompactionReport{
+            moves,
+            holes,
+            fragmented_files: self.files.iter().filter(|f| f.len() > 1).count(),
+            largest_free,
+            check_sum: self.check_sum(),
         }
     }
 }
 
-pub struct IntoIter<T>(List<T>);
+// Compacts a file system in place, returning the number of extent moves made.
+trait CompactionStrategy {
+    fn name(&self) -> &'static str;
+    fn compact(&self, fs: &mut FileSystem) -> usize;
 
-impl<T> Iterator for IntoIter<T> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        // access fields of a tuple struct numerically
-        self.0.pop()
+    fn run(&self, fs: &FileSystem) -> CompactionReport {
+        let mut fs = fs.clone();
+        let moves = self.compact(&mut fs);
+        fs.report(moves)
     }
 }
 
-pub struct Iter<'a, T> {
-    next: Option<&'a Node<T>>,
+// Moves each whole file, highest id first, into the free span picked by
+// `choose` from the candidates that fit and lie to its left.
+fn compact_whole_files(fs: &mut FileSystem, choose: fn(&[Span]) -> Option<Span>) -> usize {
+    let mut moves = 0;
+    for id in (0..fs.files.len()).rev() {
+        let h = fs.files[id][0];
+        let file = *fs.layout.get(h).unwrap();
+        if let Some((_, span)) = choose(&fs.candidates(file.len, file.start)) {
+            fs.files[id][0] = fs.allocate(span, id, file.len);
+            fs.release(h, file.len);
+            moves += 1;
+        }
+    }
+
+    moves
 }
 
-impl<'a, T> Iterator for Iter<'a, T> {
-    type Item = &'a T;
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.map(|node| {
-            self.next = node.next.as_deref();
-            &node.elem
-        })
+struct FirstFit;
+struct BestFit;
+struct WorstFit;
+struct BlockLevel;
+
+impl CompactionStrategy for FirstFit {
+    fn name(&self) -> &'static str {
+        "first-fit"
     }
-}
 
-pub struct IterMut<'a, T> {
-    next: Option<&'a mut Node<T>>,
+    fn compact(&self, fs: &mut FileSystem) -> usize {
+        compact_whole_files(fs, |c| c.iter().min().copied())
+    }
 }
 
-impl<'a, T> Iterator for IterMut<'a, T> {
-    type Item = &'a mut T;
+impl CompactionStrategy for BestFit {
+    fn name(&self) -> &'static str {
+        "best-fit"
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|node| {
-            self.next = node.next.as_deref_mut();
-            &mut node.elem
-        })
+    fn compact(&self, fs: &mut FileSystem) -> usize {
+        compact_whole_files(fs, |c| c.first().copied())
     }
 }
 
-fn main() {
-    let mut compressed = parse_input("data/input_09.txt");
-    let mut disk = expand_disk(&compressed);
-
-    let mut head: usize = 0;
-    let mut tail: usize = disk.len() - 1;
+impl CompactionStrategy for WorstFit {
+    fn name(&self) -> &'static str {
+        "worst-fit"
+    }
 
+    fn compact(&self, fs: &mut FileSystem) -> usize {
+        compact_whole_files(fs, |c| c.last().copied())
+    }
+}
 
-    while head < tail {
-        while tail > 0 && disk[tail] == -1 {
-            tail -= 1;
-        }
-    
-        while head < disk.len() && disk[head] != -1 {
-            head += 1;
-        }
+// Fills the leftmost free span with blocks taken from the end of the disk,
+// splitting files across as many extents as needed.
+impl CompactionStrategy for BlockLevel {
+    fn name(&self) -> &'static str {
+        "block-level"
+    }
 
-        if tail <= head {
-            break;
+    fn compact(&self, fs: &mut FileSystem) -> usize {
+        let mut moves = 0;
+        for id in (0..fs.files.len()).rev() {
+            loop {
+                let h = fs.files[id][0];
+                let tail = *fs.layout.get(h).unwrap();
+                let Some((_, span)) = fs.candidates(1, tail.start).into_iter().min() else {
+                    return moves;
+                };
+
+                let len = span.min(tail.len);
+                let moved = fs.allocate(span, id, len);
+                fs.files[id].push(moved);
+                moves += 1;
+
+                if fs.release(h, len) {
+                    fs.files[id].remove(0);
+                    break;
+                }
+            }
         }
 
-        disk.swap(head, tail);
+        moves
     }
+}
 
-    println!("Part 1: {:?}\n", check_sum(&disk));
-
-    let max_id = cmp::max(compressed[compressed.len() - 1].0, compressed[compressed.len() - 2].0);
-    for id in (0..max_id+1).rev() {
-        let mut size: i32 = 0;
-        for i in 0..compressed.len() {
-            if compressed[i].0 == id {
-                size = compressed[i].1;
-            }
-        }
+fn main() {
+    let compressed = parse_input("data/input_09.txt");
+    let fs = FileSystem::new(&compressed);
 
-        for i in 0..compressed.len() {
-            if compressed[i].0 == -1 && size <= compressed[i].1 {
-                compressed[i].1 -= size;
-                compressed.insert(i, (id, size));
-                compressed.insert(i, (-1, 0));
-                for j in (0..compressed.len()).rev() {
-                    if compressed[j].0 == id {
-                        compressed[j].0 = -1;
-                        break;
-                    }
-                }
+    println!("Part 1: {:?}\n", BlockLevel.run(&fs).check_sum);
+    println!("Part 2: {:?}\n", FirstFit.run(&fs).check_sum);
 
-                break;
-            }
-        }
+    let strategies: Vec<Box<dyn CompactionStrategy>> = vec![Box::new(BlockLevel), Box::new(FirstFit), Box::new(BestFit), Box::new(WorstFit)];
+    for strategy in strategies.iter() {
+        let r = strategy.run(&fs);
+        println!("{:>12}: moves {:>6}, holes {:>6}, fragmented files {:>6}, largest free {:>6}, checksum {}",
+            strategy.name(), r.moves, r.holes, r.fragmented_files, r.largest_free, r.check_sum);
     }
-
-    println!("Part 2: {:?}\n", check_sum(&expand_disk(&compressed)));
 }
 
 fn parse_input(fname: &str) -> Vec<(i32, i32)> {
@@ -182,18 +297,6 @@ fn parse_input(fname: &str) -> Vec<(i32, i32)> {
     return compressed
 }
 
-fn expand_disk(compressed: &Vec<(i32, i32)>) -> Vec<i32> {
-    let mut disk: Vec<i32> = Vec::<i32>::new();
-    for (id, size) in compressed.iter() {
-        for _ in 0..*size {
-            disk.push(*id);
-        }
-    }
-
-    return disk;
-
-}
-
 fn print_disk(disk: &Vec<i32>) {
     for i in 0..disk.len() {
         if disk[i] > -1 {
@@ -205,13 +308,48 @@ fn print_disk(disk: &Vec<i32>) {
     print!("\n");
 }
 
-fn check_sum(disk: &Vec<i32>) -> i64 {
-    let mut val: i64 = 0;
-    for i in 0..disk.len() {
-        if disk[i] > -1 {
-            val += (i as i64) * (disk[i] as i64)
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Moves, holes, fragmented files, largest free extent and checksum.
+    fn summary(strategy: &dyn CompactionStrategy, fname: &str) -> (usize, usize, usize, usize, i64) {
+        let r = strategy.run(&FileSystem::new(&parse_input(fname)));
+        (r.moves, r.holes, r.fragmented_files, r.largest_free, r.check_sum)
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(summary(&BlockLevel, "data/debug_09.txt"), (7, 0, 2, 14, 1928));
+        assert_eq!(summary(&FirstFit, "data/debug_09.txt"), (4, 5, 0, 5, 2858));
+        assert_eq!(summary(&BestFit, "data/debug_09.txt"), (4, 5, 0, 5, 2858));
+        assert_eq!(summary(&WorstFit, "data/debug_09.txt"), (4, 5, 0, 5, 2858));
+    }
+
+    #[test]
+    fn fits_differ() {
+        // 0...1..22: best-fit puts 22 in the two-block gap, the others in the
+        // three-block one, and 1 then takes the leftmost free block.
+        assert_eq!(summary(&FirstFit, "data/debug_09_2.txt"), (2, 0, 0, 5, 9));
+        assert_eq!(summary(&WorstFit, "data/debug_09_2.txt"), (2, 0, 0, 5, 9));
+        assert_eq!(summary(&BestFit, "data/debug_09_2.txt"), (2, 1, 0, 3, 23));
+        assert_eq!(summary(&BlockLevel, "data/debug_09_2.txt"), (2, 0, 0, 5, 9));
     }
 
-    return val;
+    #[test]
+    fn layout_stays_contiguous() {
+        for strategy in [&BlockLevel as &dyn CompactionStrategy, &FirstFit, &BestFit, &WorstFit] {
+            let mut fs = FileSystem::new(&parse_input("data/debug_09.txt"));
+            strategy.compact(&mut fs);
+            let mut start = 0;
+            for extent in fs.layout.iter() {
+                assert_eq!(extent.start, start, "{}", strategy.name());
+                assert!(extent.len > 0);
+                start += extent.len;
+            }
+            for (id, handles) in fs.files.iter().enumerate() {
+                assert!(handles.iter().all(|h| fs.layout.get(*h).is_some_and(|e| e.id == Some(id))));
+            }
+        }
+    }
 }
@@ -1,27 +1,64 @@
 use std::time::Instant;
 use std::fs::read_to_string;
-use std::collections::VecDeque;
 
+// Height of a `.` cell, which trails cannot enter.
+const IMPASSABLE: i32 = -1;
+
+// A trail runs from a cell at height `start` to one at height `end`, changing
+// height by exactly `step` on every move. The puzzle rule is `0 -> 9` by `+1`.
+#[derive(Clone, Copy)]
+struct TrailRule {
+    start: i32,
+    end: i32,
+    step: i32,
+}
+
+// For every cell, the set of summits reachable from it (one bit per summit)
+// and the number of distinct trails from it to any summit.
+struct TrailTable {
+    summits: Vec<Vec<u64>>,
+    paths: Vec<i64>,
+}
+
+// Usage: day10 [--trails]
 fn main() {
+    let show_trails = std::env::args().skip(1).any(|a| a == "--trails");
     let (map, dim) = parse_input("data/input_10.txt");
+    let rule = TrailRule{start: 0, end: 9, step: 1};
     
     let before = Instant::now();
-    let mut total: i32 = 0;
-    for i in 0..map.len() {
-        if map[i] == 0 {
-            total += score_trailhead(i, &map, dim);
+    let table = match rate_trails(&map, dim, rule) {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    println!("DP: {:?}", before.elapsed());
+
+    let before = Instant::now();
+    let mut total: u32 = 0;
+    for (i, h) in map.iter().enumerate() {
+        if *h == rule.start {
+            total += table.summits[i].iter().map(|w| w.count_ones()).sum::<u32>();
         }
     }
     println!("Part 1: {:?}, Time: {:?}", total, before.elapsed());
     
     let before = Instant::now();
-    let mut total: i32 = 0;
-    for i in 0..map.len() {
-        if map[i] == 0 {
-            total += count_paths(i, &map, dim);
+    let mut total: i64 = 0;
+    for (i, h) in map.iter().enumerate() {
+        if *h == rule.start {
+            total += table.paths[i];
         }
     }
     println!("Part 2: {:?}, Time: {:?}", total, before.elapsed());
+
+    if let Some(head) = map.iter().position(|h| *h == rule.start).filter(|_| show_trails) {
+        for trail in export_trails(head, 3, &map, dim, rule, &table) {
+            println!("{:?}", trail);
+        }
+    }
 }
 
 fn parse_input(fname: &str) -> (Vec<i32>, (usize, usize)) {
@@ -37,60 +74,107 @@ fn parse_input(fname: &str) -> (Vec<i32>, (usize, usize)) {
     let mut map: Vec<i32> = vec![0; (dim.0 * dim.1) as usize];
     for (i, line) in read_to_string(fname).unwrap().lines().enumerate() {
         for (j, c) in line.chars().enumerate() {
-            map[i * (dim.1 as usize) + j] = c.to_string().parse::<i32>().unwrap();
+            map[i * (dim.1 as usize) + j] = match c {
+                '.' => IMPASSABLE,
+                _ => c.to_string().parse::<i32>().unwrap(),
+            };
         }
     }
 
     return (map, dim)
 }
 
-fn score_trailhead(p: usize, map: &Vec<i32>, dim: (usize, usize)) -> i32 {
-    let mut score = 0;
-    let mut visited: Vec<i32> = vec![0; map.len()];
+// Heights visited by a trail, from `start` to `end` inclusive. Empty if `end`
+// can't be reached from `start` in whole steps, and an error if the rule
+// doesn't change height at all.
+fn levels(rule: TrailRule) -> Result<Vec<i32>, String> {
+    if rule.step == 0 {
+        return Err("trail step must be non-zero".to_string());
+    }
+    let span = rule.end - rule.start;
+    if span % rule.step != 0 || span / rule.step < 0 {
+        return Ok(Vec::new());
+    }
 
+    Ok((0..=span / rule.step).map(|k| rule.start + k * rule.step).collect())
+}
 
-    let mut q: VecDeque<usize> = VecDeque::<usize>::new();
-    q.push_front(p);
+fn is_step(map: &[i32], from: usize, to: usize, rule: TrailRule) -> bool {
+    map[from] != IMPASSABLE && map[to] != IMPASSABLE && map[to] == map[from] + rule.step
+}
 
-    while q.len() > 0 {
-        let curr = q.pop_back().unwrap();
-        for n in neighbors(curr, dim) {
-            if map[n] - map[curr] == 1 && visited[n] == 0 {
-                visited[n] = 1;
-                if map[n] == 9 {
-                    score += 1;
-                } else {
-                    q.push_front(n);
+// Fills the table in a single pass from the end height back to the start. A
+// cell's summits and trail count are the union and sum over its neighbours one
+// step further along, which are always complete by the time the cell is visited.
+fn rate_trails(map: &[i32], dim: (usize, usize), rule: TrailRule) -> Result<TrailTable, String> {
+    let levels = levels(rule)?;
+    let mut by_level: Vec<Vec<usize>> = vec![Vec::new(); levels.len()];
+    for (i, h) in map.iter().enumerate() {
+        if *h != IMPASSABLE {
+            if let Some(k) = levels.iter().position(|l| l == h) {
+                by_level[k].push(i);
+            }
+        }
+    }
+
+    let ends: &[usize] = by_level.last().map_or(&[], |v| v);
+    let words = ends.len().div_ceil(64);
+    let mut summits: Vec<Vec<u64>> = vec![vec![0; words]; map.len()];
+    let mut paths: Vec<i64> = vec![0; map.len()];
+
+    for (bit, p) in ends.iter().enumerate() {
+        summits[*p][bit / 64] |= 1 << (bit % 64);
+        paths[*p] = 1;
+    }
+
+    for level in by_level.iter().rev().skip(1) {
+        for p in level.iter() {
+            for n in neighbors(*p, dim) {
+                if is_step(map, *p, n, rule) {
+                    let reach = summits[n].clone();
+                    for (a, b) in summits[*p].iter_mut().zip(reach) {
+                        *a |= b;
+                    }
+                    paths[*p] += paths[n];
                 }
             }
         }
     }
 
-    return score;
+    Ok(TrailTable{summits, paths})
 }
 
-fn count_paths(src: usize, map: &Vec<i32>, dim: (usize, usize)) -> i32 {
-    let mut paths: i32 = 0;
-
-    let mut q: VecDeque<Vec<usize>> = VecDeque::<Vec<usize>>::new();
-    q.push_front(vec![src]);
-
-    while q.len() > 0 {
-        let curr = q.pop_back().unwrap();
-        for n in neighbors(curr[curr.len() - 1], dim) {
-            if map[n] == map[curr[curr.len() - 1]] + 1 {
-                if map[n] == 9 {
-                    paths += 1;
-                } else {
-                    let mut next = curr.clone();
-                    next.push(n);
-                    q.push_front(next);
-                }
+// Lists up to `k` distinct trails from `head` as `(row, col)` coordinates. The
+// search only follows cells with a non-zero trail count, so every branch it
+// takes ends at a summit.
+fn export_trails(head: usize, k: usize, map: &[i32], dim: (usize, usize), rule: TrailRule, table: &TrailTable) -> Vec<Vec<(usize, usize)>> {
+    let mut trails: Vec<Vec<(usize, usize)>> = Vec::new();
+    if map[head] != rule.start || table.paths[head] == 0 {
+        return trails;
+    }
+
+    let mut stack: Vec<Vec<usize>> = vec![vec![head]];
+    while let Some(trail) = stack.pop() {
+        if trails.len() == k {
+            break;
+        }
+
+        let last = trail[trail.len() - 1];
+        if map[last] == rule.end {
+            trails.push(trail.iter().map(|i| (i / dim.1, i % dim.1)).collect());
+            continue;
+        }
+
+        for n in neighbors(last, dim).into_iter().rev() {
+            if is_step(map, last, n, rule) && table.paths[n] > 0 {
+                let mut next = trail.clone();
+                next.push(n);
+                stack.push(next);
             }
         }
     }
-     
-    return paths;
+
+    trails
 }
 
 fn neighbors(ind: usize, dim: (usize, usize)) -> Vec<usize> {
@@ -133,3 +217,74 @@ fn print_trails(map: &Vec<i32>, visited: &Vec<i32>, dim: (usize, usize)) {
     }
     print!("\n");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: TrailRule = TrailRule{start: 0, end: 9, step: 1};
+
+    // Sum of trailhead scores and of trailhead ratings.
+    fn totals(fname: &str, rule: TrailRule) -> (u32, i64) {
+        let (map, dim) = parse_input(fname);
+        let table = rate_trails(&map, dim, rule).unwrap();
+        let heads = (0..map.len()).filter(|i| map[*i] == rule.start);
+        heads.fold((0, 0), |(score, rating), i| {
+            (score + table.summits[i].iter().map(|w| w.count_ones()).sum::<u32>(), rating + table.paths[i])
+        })
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(totals("data/debug_10.txt", PUZZLE), (36, 81));
+    }
+
+    #[test]
+    fn impassable_cells() {
+        assert_eq!(totals("data/debug_10_2.txt", PUZZLE).0, 4);
+        assert_eq!(totals("data/debug_10_3.txt", PUZZLE).1, 3);
+    }
+
+    #[test]
+    fn other_rules() {
+        // Walking down from the summits finds the same trails.
+        assert_eq!(totals("data/debug_10.txt", TrailRule{start: 9, end: 0, step: -1}).1, 81);
+        assert_eq!(totals("data/debug_10.txt", TrailRule{start: 0, end: 9, step: 2}), (0, 0));
+        assert_eq!(levels(TrailRule{start: 1, end: 7, step: 3}), Ok(vec![1, 4, 7]));
+        assert_eq!(levels(TrailRule{start: 7, end: 1, step: 3}), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn zero_step_is_an_error() {
+        let (map, dim) = parse_input("data/debug_10.txt");
+        assert!(levels(TrailRule{start: 0, end: 9, step: 0}).is_err());
+        assert!(rate_trails(&map, dim, TrailRule{start: 0, end: 9, step: 0}).is_err());
+    }
+
+    #[test]
+    fn exported_trails() {
+        let (map, dim) = parse_input("data/debug_10.txt");
+        let table = rate_trails(&map, dim, PUZZLE).unwrap();
+        for head in (0..map.len()).filter(|i| map[*i] == 0) {
+            for k in [0, 1, 3, 1000] {
+                let trails = export_trails(head, k, &map, dim, PUZZLE, &table);
+                assert_eq!(trails.len() as i64, table.paths[head].min(k as i64));
+
+                let mut unique = trails.clone();
+                unique.sort();
+                unique.dedup();
+                assert_eq!(unique.len(), trails.len());
+
+                for trail in trails.iter() {
+                    assert_eq!(trail[0], (head / dim.1, head % dim.1));
+                    for (h, (r, c)) in trail.iter().enumerate() {
+                        assert_eq!(map[r * dim.1 + c], h as i32);
+                    }
+                    for w in trail.windows(2) {
+                        assert_eq!(w[0].0.abs_diff(w[1].0) + w[0].1.abs_diff(w[1].1), 1);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,110 +1,429 @@
 use std::time::Instant;
 use std::fs::read_to_string;
 use std::collections::HashMap;
-use std::cmp::max;
+use std::fmt;
 
-fn main() {
-    let mut stones: HashMap<i64, i64> = parse_input("data/input_11.txt");
+// Maps one stone to the stones it becomes after a single blink, or `None` if
+// a new value does not fit in a `u64`.
+trait RuleSet {
+    fn blink(&self, stone: u64) -> Option<Vec<u64>>;
+}
 
-    let before = Instant::now();
-    for _ in 0..25 {
-        stones = step(&stones);
-    }
-    println!("Part 1: {:?}, Time: {:?}", count_stones(&stones), before.elapsed());
-    
-    let mut stones: HashMap<i64, i64> = parse_input("data/input_11.txt");
+// The puzzle rules: 0 becomes 1, an even number of digits splits in half,
+// anything else is multiplied by 2024.
+struct Puzzle;
 
-    let before = Instant::now();
-    for _ in 0..75 {
-        stones = step(&stones);
+impl RuleSet for Puzzle {
+    fn blink(&self, stone: u64) -> Option<Vec<u64>> {
+        let pow = digits(stone);
+        if stone == 0 {
+            Some(vec![1])
+        } else if pow.is_multiple_of(2) {
+            let half = 10_u64.pow(pow / 2);
+            Some(vec![stone / half, stone % half])
+        } else {
+            stone.checked_mul(2024).map(|k| vec![k])
+        }
     }
-    println!("Part 2: {:?}, Time: {:?}", count_stones(&stones), before.elapsed());
 }
 
-fn parse_input(fname: &str) -> HashMap<i64, i64> {
-    let mut data: HashMap<i64, i64> = HashMap::<i64, i64>::new();
-    for line in read_to_string(fname).unwrap().lines() {
-        for field in line.split_whitespace() {
-            let key: i64 = field.parse::<i64>().unwrap();
+fn digits(n: u64) -> u32 {
+    n.checked_ilog10().map_or(1, |d| d + 1)
+}
 
-            if let Some(x) = data.get_mut(&key) {
-                *x = *x + 1;
-            } else {
-                data.insert(key, 1);
+// Exact stone count. Counts only ever grow by addition, so this is a bare
+// little-endian vector of base 2^64 limbs with no upper bound.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct BigCount(Vec<u64>);
+
+impl BigCount {
+    fn from_u64(n: u64) -> Self {
+        BigCount(if n == 0 { Vec::new() } else { vec![n] })
+    }
+
+    fn add_assign(&mut self, other: &BigCount) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        let mut carry = false;
+        for (i, limb) in self.0.iter_mut().enumerate() {
+            let (sum, c1) = limb.overflowing_add(other.0.get(i).copied().unwrap_or(0));
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 || c2;
+            if !carry && i >= other.0.len() {
+                break;
             }
         }
+        if carry {
+            self.0.push(1);
+        }
     }
 
-    return data;
+    fn rem(&self, m: u64) -> u64 {
+        self.0.iter().rev().fold(0, |r, limb| ((((r as u128) << 64) | *limb as u128) % m as u128) as u64)
+    }
+
+    fn bits(&self) -> u64 {
+        self.0.last().map_or(0, |top| 64 * self.0.len() as u64 - top.leading_zeros() as u64)
+    }
 }
 
-fn step(stones: &HashMap<i64, i64>) -> HashMap<i64, i64>{
-    let mut new_stones: HashMap<i64, i64> = HashMap::<i64, i64>::new();
-    for (key, val) in stones.iter(){
-        let pow = ((*key as f32).log(10.0).floor() + 1.0)as u32;
-        if *key == 0 {
-            if let Some(x) = new_stones.get_mut(&1) {
-                *x = *x + *val;
-            } else {
-                new_stones.insert(1, *val);
+impl fmt::Display for BigCount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        let mut limbs = self.0.clone();
+        let mut chunks: Vec<u64> = Vec::new();
+        while !limbs.is_empty() {
+            let mut r: u128 = 0;
+            for limb in limbs.iter_mut().rev() {
+                let cur = (r << 64) | *limb as u128;
+                *limb = (cur / CHUNK as u128) as u64;
+                r = cur % CHUNK as u128;
             }
-        } else if pow % 2 == 0 {
-            let k1 =  *key / 10_i64.pow(pow/2);
-            if let Some(x) = new_stones.get_mut(&k1) {
-                *x = *x + *val;
-            } else {
-                new_stones.insert(k1, *val);
+            chunks.push(r as u64);
+            while limbs.last() == Some(&0) {
+                limbs.pop();
             }
+        }
 
-            let k2 = *key % 10_i64.pow(pow/2);
-            if let Some(x) = new_stones.get_mut(&k2) {
-                *x = *x + *val;
-            } else {
-                new_stones.insert(k2, *val);
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((top, rest)) => {
+                write!(f, "{}", top)?;
+                rest.iter().rev().try_for_each(|c| write!(f, "{:019}", c))
             }
-        } else {
-            let k = *key * 2024;
-            if let Some(x) = new_stones.get_mut(&k) {
-                *x = *x + *val;
-            } else {
-                new_stones.insert(k, *val);
+        }
+    }
+}
+
+// Counts the stones level by level, carrying how many stones of each value
+// there are, so the cost is linear in the number of blinks. The outcome of a
+// blink is memoized per value. `None` means a stone value overflowed.
+struct Evolution<R: RuleSet> {
+    rules: R,
+    memo: HashMap<u64, Option<Vec<u64>>>,
+}
+
+impl<R: RuleSet> Evolution<R> {
+    fn new(rules: R) -> Self {
+        Evolution{rules, memo: HashMap::new()}
+    }
+
+    fn blink(&mut self, stone: u64) -> Option<Vec<u64>> {
+        let rules = &self.rules;
+        self.memo.entry(stone).or_insert_with(|| rules.blink(stone)).clone()
+    }
+
+    fn count(&mut self, stone: u64, blinks: usize) -> Option<BigCount> {
+        self.count_all(&[stone], blinks)
+    }
+
+    fn count_all(&mut self, stones: &[u64], blinks: usize) -> Option<BigCount> {
+        let mut level: HashMap<u64, BigCount> = HashMap::new();
+        for s in stones.iter() {
+            level.entry(*s).or_default().add_assign(&BigCount::from_u64(1));
+        }
+
+        for _ in 0..blinks {
+            let mut next: HashMap<u64, BigCount> = HashMap::with_capacity(level.len());
+            for (s, n) in level.iter() {
+                for t in self.blink(*s)? {
+                    next.entry(t).or_default().add_assign(n);
+                }
+            }
+            level = next;
+        }
+
+        Some(level.values().fold(BigCount::default(), |mut acc, n| {
+            acc.add_assign(n);
+            acc
+        }))
+    }
+}
+
+// Closure analysis reports counts modulo the Mersenne prime 2^61 - 1; exact
+// counts for huge blink numbers would not fit in memory.
+const MODULUS: u64 = (1 << 61) - 1;
+
+fn add_mod(a: u64, b: u64) -> u64 {
+    let s = a + b;
+    if s >= MODULUS { s - MODULUS } else { s }
+}
+
+fn sub_mod(a: u64, b: u64) -> u64 {
+    if a >= b { a - b } else { a + MODULUS - b }
+}
+
+fn mul_mod(a: u64, b: u64) -> u64 {
+    let x = a as u128 * b as u128;
+    let r = (x as u64 & MODULUS) + (x >> 61) as u64;
+    if r >= MODULUS { r - MODULUS } else { r }
+}
+
+fn pow_mod(mut b: u64, mut e: u64) -> u64 {
+    let mut r = 1;
+    while e > 0 {
+        if e & 1 == 1 {
+            r = mul_mod(r, b);
+        }
+        b = mul_mod(b, b);
+        e >>= 1;
+    }
+
+    r
+}
+
+fn inv_mod(a: u64) -> u64 {
+    pow_mod(a, MODULUS - 2)
+}
+
+// Every stone value reachable from the input, with the values each one turns
+// into on a blink given as indices into `values`. Together the rows form a
+// sparse transition matrix.
+struct Closure {
+    values: Vec<u64>,
+    index: HashMap<u64, usize>,
+    transitions: Vec<Vec<usize>>,
+}
+
+impl Closure {
+    // Returns `None` if some reachable value overflows a `u64`.
+    fn discover<R: RuleSet>(rules: &R, stones: &[u64]) -> Option<Self> {
+        let mut closure = Closure{values: Vec::new(), index: HashMap::new(), transitions: Vec::new()};
+        let mut stack: Vec<u64> = Vec::<u64>::new();
+        for s in stones.iter() {
+            closure.intern(*s, &mut stack);
+        }
+
+        while let Some(s) = stack.pop() {
+            let next = rules.blink(s)?;
+            let row: Vec<usize> = next.into_iter().map(|n| closure.intern(n, &mut stack)).collect();
+            let i = closure.index[&s];
+            closure.transitions[i] = row;
+        }
+
+        Some(closure)
+    }
+
+    fn intern(&mut self, stone: u64, stack: &mut Vec<u64>) -> usize {
+        if let Some(i) = self.index.get(&stone) {
+            return *i;
+        }
+
+        self.values.push(stone);
+        self.transitions.push(Vec::new());
+        self.index.insert(stone, self.values.len() - 1);
+        stack.push(stone);
+
+        self.values.len() - 1
+    }
+
+    // Stone counts modulo `MODULUS` after 0, 1, .., `len - 1` blinks, found by
+    // pushing a vector of per-value counts through the sparse transitions.
+    fn sequence(&self, stones: &[u64], len: usize) -> Vec<u64> {
+        let mut v: Vec<u64> = vec![0; self.values.len()];
+        for s in stones.iter() {
+            v[self.index[s]] += 1;
+        }
+
+        let mut seq: Vec<u64> = Vec::with_capacity(len);
+        for _ in 0..len {
+            seq.push(v.iter().fold(0, |acc, n| add_mod(acc, *n)));
+            let mut next: Vec<u64> = vec![0; v.len()];
+            for (i, row) in self.transitions.iter().enumerate() {
+                for j in row.iter() {
+                    next[*j] = add_mod(next[*j], v[i]);
+                }
             }
+            v = next;
         }
+
+        seq
     }
 
-    return new_stones;
+    // Number of stones, modulo `MODULUS`, after `blinks` blinks. The counts
+    // obey a linear recurrence whose order is at most the closure size `n`
+    // (the degree of the transition matrix's minimal polynomial), so `2n`
+    // terms pin it down via Berlekamp-Massey. The requested term then comes
+    // from `x^blinks` modulo the recurrence's characteristic polynomial, which
+    // costs O(order^2 log blinks).
+    fn count_mod(&self, stones: &[u64], blinks: u64) -> u64 {
+        let terms = 2 * self.values.len() + 2;
+        if blinks < terms as u64 {
+            return self.sequence(stones, blinks as usize + 1)[blinks as usize];
+        }
+
+        let seq = self.sequence(stones, terms);
+        let rec = berlekamp_massey(&seq);
+        let r = x_pow_mod(&rec, blinks);
+        r.iter().zip(seq.iter()).fold(0, |acc, (a, b)| add_mod(acc, mul_mod(*a, *b)))
+    }
 }
 
-fn count_stones(stones: &HashMap<i64, i64>) -> i64 {
-    let mut total: i64 = 0;
-    for (_key, val) in stones.iter() {
-        total += *val;
+// Shortest recurrence `s[k] = rec[0] s[k-1] + .. + rec[l-1] s[k-l]` that
+// generates `s` modulo `MODULUS`.
+fn berlekamp_massey(s: &[u64]) -> Vec<u64> {
+    let mut c: Vec<u64> = vec![1];
+    let mut b: Vec<u64> = vec![1];
+    let (mut l, mut m, mut last) = (0, 1, 1);
+    for n in 0..s.len() {
+        let d = (1..=l).fold(s[n], |d, i| add_mod(d, mul_mod(c[i], s[n - i])));
+        if d == 0 {
+            m += 1;
+            continue;
+        }
+
+        let coef = mul_mod(d, inv_mod(last));
+        let prev = c.clone();
+        if c.len() < b.len() + m {
+            c.resize(b.len() + m, 0);
+        }
+        for (i, bi) in b.iter().enumerate() {
+            c[i + m] = sub_mod(c[i + m], mul_mod(coef, *bi));
+        }
+        if 2 * l <= n {
+            l = n + 1 - l;
+            b = prev;
+            last = d;
+            m = 1;
+        } else {
+            m += 1;
+        }
+    }
+
+    c.resize(l + 1, 0);
+    c[1..].iter().map(|v| sub_mod(0, *v)).collect()
+}
+
+// Coefficients of `x^e` reduced modulo `x^l - rec[0] x^(l-1) - .. - rec[l-1]`,
+// lowest degree first, so that term `e` is their dot product with the first
+// `l` terms of the sequence.
+fn x_pow_mod(rec: &[u64], e: u64) -> Vec<u64> {
+    let l = rec.len();
+    if l == 0 {
+        return Vec::new();
+    }
+
+    // Folds every coefficient of degree `l` and above back onto lower ones.
+    let reduce = |mut p: Vec<u64>| -> Vec<u64> {
+        for i in (l..p.len()).rev() {
+            let top = p[i];
+            if top != 0 {
+                for (j, c) in rec.iter().enumerate() {
+                    p[i - 1 - j] = add_mod(p[i - 1 - j], mul_mod(top, *c));
+                }
+            }
+        }
+        p.truncate(l);
+        p
+    };
+
+    let mut r: Vec<u64> = vec![0; l];
+    r[0] = 1;
+    let mut r = reduce(r);
+    for bit in (0..64 - e.leading_zeros()).rev() {
+        let mut sq: Vec<u64> = vec![0; 2 * l];
+        for (i, a) in r.iter().enumerate().filter(|(_, a)| **a != 0) {
+            for (j, b) in r.iter().enumerate() {
+                sq[i + j] = add_mod(sq[i + j], mul_mod(*a, *b));
+            }
+        }
+        r = reduce(sq);
+        if (e >> bit) & 1 == 1 {
+            r.insert(0, 0);
+            r = reduce(r);
+        }
     }
 
-    return total;
+    r
 }
 
-fn print_stones(stones: &HashMap<i64, i64>) {
-    let mut keys: Vec<i64> = Vec::<i64>::new();
-    let mut max_pow_k: u32 = 0;
-    let mut max_pow_v: u32 = 0;
-    for k in stones.keys() {
-        keys.push(*k);
-        max_pow_k = max(max_pow_k, ((*k as f32).log(10.0).floor() + 1.0) as u32);
-        max_pow_v = max(max_pow_v, ((*stones.get(k).unwrap() as f32).log(10.0).floor() + 1.0) as u32);
+fn main() {
+    let stones: Vec<u64> = parse_input("data/input_11.txt");
+    let mut evolution = Evolution::new(Puzzle);
+
+    for (part, blinks) in [(1, 25), (2, 75)] {
+        let before = Instant::now();
+        match evolution.count_all(&stones, blinks) {
+            Some(n) => println!("Part {}: {}, Time: {:?}", part, n, before.elapsed()),
+            None => println!("Part {}: stone value overflowed u64", part),
+        }
     }
-    keys.sort();
 
-    for k in keys.iter() {
-        let mut pad = max_pow_k - ((*k as f32).log(10.0).floor() + 1.0) as u32;
-        if *k == 0 {
-            pad -= 1;
+    for s in stones.iter() {
+        match evolution.count(*s, 75) {
+            Some(n) => println!("{:>10}: {}", s, n),
+            None => println!("{:>10}: stone value overflowed u64", s),
         }
-        let p1 = (0..pad).map(|_| " ").collect::<String>();
+    }
 
-        let pad = max_pow_v - ((*stones.get(k).unwrap() as f32).log(10.0).floor() + 1.0) as u32;
-        let p2 = (0..pad).map(|_| " ").collect::<String>();
+    let before = Instant::now();
+    match evolution.count_all(&stones, 2000) {
+        Some(n) => println!("2000 blinks: {} bits, Time: {:?}", n.bits(), before.elapsed()),
+        None => println!("2000 blinks: stone value overflowed u64"),
+    }
+
+    let before = Instant::now();
+    match Closure::discover(&Puzzle, &stones) {
+        Some(closure) => {
+            let check = evolution.count_all(&stones, 75).map(|n| n.rem(MODULUS));
+            println!("Closure: {} values, 75 blinks: {} (memoized {:?})", closure.values.len(), closure.count_mod(&stones, 75), check);
+            println!("10^15 blinks: {} (mod {}), Time: {:?}", closure.count_mod(&stones, 1_000_000_000_000_000), MODULUS, before.elapsed());
+        }
+        None => println!("Closure: stone value overflowed u64"),
+    }
+}
+
+fn parse_input(fname: &str) -> Vec<u64> {
+    let mut data: Vec<u64> = Vec::<u64>::new();
+    for line in read_to_string(fname).unwrap().lines() {
+        for field in line.split_whitespace() {
+            data.push(field.parse::<u64>().unwrap());
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn big_count_carries_and_prints() {
+        let mut n = BigCount::from_u64(u64::MAX);
+        n.add_assign(&BigCount::from_u64(1));
+        assert_eq!(n, BigCount(vec![0, 1]));
+        assert_eq!(n.to_string(), "18446744073709551616");
+        assert_eq!(n.rem(MODULUS), 8);
+        assert_eq!(BigCount::default().to_string(), "0");
+    }
+
+    #[test]
+    fn example_counts() {
+        let mut evolution = Evolution::new(Puzzle);
+        assert_eq!(evolution.count_all(&[125, 17], 6).unwrap().to_string(), "22");
+        assert_eq!(evolution.count_all(&[125, 17], 25).unwrap().to_string(), "55312");
+        assert_eq!(evolution.count_all(&[125, 17], 75).unwrap().to_string(), "65601038650482");
+    }
+
+    #[test]
+    fn counts_past_u128() {
+        let mut evolution = Evolution::new(Puzzle);
+        let n = evolution.count(0, 400).unwrap();
+        assert!(n.bits() > 128);
+        let closure = Closure::discover(&Puzzle, &[0]).unwrap();
+        assert_eq!(closure.count_mod(&[0], 400), n.rem(MODULUS));
+    }
 
-        println!("{}{:?}: {}{:?}", p1, k, p2, stones.get(k).unwrap());
+    #[test]
+    fn recurrence_matches_direct_sequence() {
+        let stones = [125, 17];
+        let closure = Closure::discover(&Puzzle, &stones).unwrap();
+        let far = 4 * closure.values.len() + 7;
+        let seq = closure.sequence(&stones, far + 1);
+        assert_eq!(closure.count_mod(&stones, 75), seq[75]);
+        assert_eq!(closure.count_mod(&stones, far as u64), seq[far]);
     }
 }
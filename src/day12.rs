@@ -1,4 +1,6 @@
-use std::fs::read_to_string;
+use std::fs::{read_to_string, write};
+use std::fmt::Write;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 struct Matrix {
     rows: usize,
@@ -51,52 +53,100 @@ impl Matrix {
     }
 }
 
+struct Region {
+    plant: char,
+    cells: Vec<(usize, usize)>,
+    perimeter: u32,
+    sides: u32,
+    // Inclusive `(top-left, bottom-right)` corners.
+    bbox: ((usize, usize), (usize, usize)),
+    holes: u32,
+    boundaries: Vec<BoundaryLoop>,
+}
+
+// Unit steps along the lattice of cell corners: east, south, west, north.
+const DIRS: [(i32, i32); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+// A unit segment of fence starting at lattice point `from` (the top-left
+// corner of cell `(r, c)` is point `(r, c)`) and heading in `DIRS[dir]`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct Edge {
+    from: (usize, usize),
+    dir: usize,
+}
+
+impl Edge {
+    fn to(&self) -> (usize, usize) {
+        ((self.from.0 as i32 + DIRS[self.dir].0) as usize, (self.from.1 as i32 + DIRS[self.dir].1) as usize)
+    }
+}
+
+// A closed fence loop, walked with the region on its right. The outer
+// boundary runs clockwise and each inner boundary anticlockwise.
+struct BoundaryLoop {
+    edges: Vec<Edge>,
+    outer: bool,
+    sides: u32,
+}
+
+impl Region {
+    fn area(&self) -> u32 {
+        self.cells.len() as u32
+    }
+}
+
+// Usage: day12 [--svg=PATH]
 fn main() {
     let data = parse_input("data/input_12.txt");
-    let mut visited = Matrix::new(data.rows, data.cols);
-    let mut total = 0;
+    let regions = find_regions(&data);
 
-    for r in 0..data.rows {
-        for c in 0..data.cols {
-            if *visited.get(r, c).unwrap() == 0 {
-                let (area, perimeter, _) = flood_fill(r, c, &data, &mut visited);
-                let plant: char = char::from_u32(*data.get(r, c).unwrap() as u32 + 'A' as u32).unwrap();
-                println!("A region of {} plants with price {:?} * {:?} = {:?}.", plant, area, perimeter, area * perimeter);
-                total += area * perimeter;
-            }
-        }
+    for region in regions.iter() {
+        let inner = region.boundaries.iter().filter(|b| !b.outer).count();
+        println!("A region of {} plants with area {:?}, perimeter {:?}, {:?} sides, {:?} holes ({:?} inner fences), spanning {:?}.",
+            region.plant, region.area(), region.perimeter, region.sides, region.holes, inner, region.bbox);
     }
-    println!("Part 1: {:?}", total);
-    
-    let mut visited = Matrix::new(data.rows, data.cols);
-    let mut total = 0;
+    println!("Part 1: {:?}", total_price(&regions, |r| r.area() * r.perimeter));
+    println!("Part 2: {:?}", total_price(&regions, |r| r.area() * r.sides));
+
+    if let Some(path) = std::env::args().skip(1).find_map(|a| a.strip_prefix("--svg=").map(String::from)) {
+        write(&path, render_svg(&data, &regions)).unwrap();
+        println!("Wrote {}", path);
+    }
+}
 
-    for r in 0..data.rows {
-        for c in 0..data.cols {
+fn find_regions(m: &Matrix) -> Vec<Region> {
+    let mut visited = Matrix::new(m.rows, m.cols);
+    let mut regions: Vec<Region> = Vec::<Region>::new();
+
+    for r in 0..m.rows {
+        for c in 0..m.cols {
             if *visited.get(r, c).unwrap() == 0 {
-                let (area, _, corners) = flood_fill(r, c, &data, &mut visited);
-                let plant: char = char::from_u32(*data.get(r, c).unwrap() as u32 + 'A' as u32).unwrap();
-                println!("A region of {} plants with price {:?} * {:?} = {:?}.", plant, area, corners, area * corners);
-                total += area * corners;
+                regions.push(flood_fill(r, c, m, &mut visited));
             }
         }
     }
-    println!("Part 1: {:?}", total);
+
+    regions
+}
+
+fn total_price(regions: &[Region], formula: fn(&Region) -> u32) -> u32 {
+    regions.iter().map(formula).sum()
 }
 
 fn parse_input(fname: &str) -> Matrix {
+    let text = read_to_string(fname).unwrap();
     let mut rows: usize = 0;
     let mut cols: usize = 0;
 
-    for line in read_to_string(fname).unwrap().lines() {
+    for line in text.lines() {
         rows += 1;
         cols = line.len();
     }
 
     let mut data: Matrix = Matrix::new(rows, cols);
-    for (r, line) in read_to_string(fname).unwrap().lines().enumerate() {
+    for (r, line) in text.lines().enumerate() {
         for (c, val) in line.chars().enumerate() {
-            *data.get_mut(r, c).unwrap() = (val as u32 - 'A' as u32) as i32;
+            *data.get_mut(r, c).unwrap() = val as i32;
         }
     }
 
@@ -106,28 +156,84 @@ fn parse_input(fname: &str) -> Matrix {
 fn print_pretty(m: &Matrix) {
     for r in 0..m.rows {
         for c in 0..m.cols {
-            print!("{}", char::from_u32(*m.get(r, c).unwrap() as u32 + 'A' as u32).unwrap());
+            print!("{}", char::from_u32(*m.get(r, c).unwrap() as u32).unwrap());
         }
         print!("\n");
     }
 }
 
-fn flood_fill(r: usize, c: usize, m: &Matrix, v: &mut Matrix) -> (u32, u32, u32) {
-    let mut area: u32 = 0;
+// Pixel size of one garden plot in the SVG render.
+const CELL: usize = 16;
+
+impl BoundaryLoop {
+    // Merges runs of edges heading the same way into straight sides, returned
+    // as `(start, end)` lattice points.
+    fn merged_sides(&self) -> Vec<((usize, usize), (usize, usize))> {
+        let n = self.edges.len();
+        let first = (0..n).find(|i| self.edges[(i + n - 1) % n].dir != self.edges[*i].dir).unwrap();
+
+        let mut sides: Vec<((usize, usize), (usize, usize))> = Vec::new();
+        for k in 0..n {
+            let e = self.edges[(first + k) % n];
+            match sides.last_mut() {
+                Some(side) if k > 0 && self.edges[(first + k - 1) % n].dir == e.dir => side.1 = e.to(),
+                _ => sides.push((e.from, e.to())),
+            }
+        }
+
+        sides
+    }
+}
+
+// Renders the garden as SVG. Each region gets its own fill colour, every unit
+// of fence is drawn as a thin black line, and the merged sides that part 2
+// prices are overlaid in alternating colours with a dot at each corner, so a
+// miscounted side shows up as two adjacent segments of the same colour.
+fn render_svg(m: &Matrix, regions: &[Region]) -> String {
+    let mut svg = String::new();
+    writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#, m.cols * CELL + 2 * CELL, m.rows * CELL + 2 * CELL).unwrap();
+    writeln!(svg, r#"<g transform="translate({CELL},{CELL})" font-family="monospace" font-size="{}" text-anchor="middle">"#, CELL * 3 / 4).unwrap();
+
+    for (i, region) in regions.iter().enumerate() {
+        // Golden-angle hue steps keep neighbouring region ids visually apart.
+        let hue = (i * 137) % 360;
+        for (r, c) in region.cells.iter() {
+            writeln!(svg, r#"<rect x="{}" y="{}" width="{CELL}" height="{CELL}" fill="hsl({hue},60%,75%)"/>"#, c * CELL, r * CELL).unwrap();
+            writeln!(svg, r#"<text x="{}" y="{}">{}</text>"#, c * CELL + CELL / 2, r * CELL + CELL * 3 / 4, region.plant).unwrap();
+        }
+    }
+
+    for region in regions.iter() {
+        for boundary in region.boundaries.iter() {
+            for e in boundary.edges.iter() {
+                let (a, b) = (e.from, e.to());
+                writeln!(svg, r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="1"/>"#, a.1 * CELL, a.0 * CELL, b.1 * CELL, b.0 * CELL).unwrap();
+            }
+
+            for (k, (a, b)) in boundary.merged_sides().into_iter().enumerate() {
+                let color = if k % 2 == 0 { "crimson" } else { "royalblue" };
+                writeln!(svg, r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{color}" stroke-width="3" stroke-linecap="round" opacity="0.8"/>"#, a.1 * CELL, a.0 * CELL, b.1 * CELL, b.0 * CELL).unwrap();
+                writeln!(svg, r#"<circle cx="{}" cy="{}" r="2" fill="black"/>"#, a.1 * CELL, a.0 * CELL).unwrap();
+            }
+        }
+    }
+
+    writeln!(svg, "</g>\n</svg>").unwrap();
+    svg
+}
+
+fn flood_fill(r: usize, c: usize, m: &Matrix, v: &mut Matrix) -> Region {
+    let mut cells: Vec<(usize, usize)> = Vec::<(usize, usize)>::new();
     let mut perimeter: u32 = 0;
-    let mut corners: u32 = 0;
 
     let mut q: VecDeque<(usize, usize)> = VecDeque::<(usize, usize)>::new();
     q.push_front((r, c));
 
-    while q.len() > 0 {
-        let p = q.pop_back().unwrap();
-
+    while let Some(p) = q.pop_back() {
         if *v.get(p.0, p.1).unwrap() == 0 {
             *v.get_mut(p.0, p.1).unwrap() = 1;
-            area += 1;
+            cells.push(p);
             perimeter += 4;
-            corners += count_corners(p, m);
    
             for n in m.get_neighbors(p.0, p.1) {
                 if *m.get(r, c).unwrap() == *m.get(n.0, n.1).unwrap() {
@@ -141,9 +247,127 @@ fn flood_fill(r: usize, c: usize, m: &Matrix, v: &mut Matrix) -> (u32, u32, u32)
         }
     }
 
-    return (area, perimeter, corners);
+    let bbox = (
+        (cells.iter().map(|p| p.0).min().unwrap(), cells.iter().map(|p| p.1).min().unwrap()),
+        (cells.iter().map(|p| p.0).max().unwrap(), cells.iter().map(|p| p.1).max().unwrap()),
+    );
+    let holes = count_holes(&cells, bbox);
+    let boundaries = trace_boundaries(&cells);
+
+    Region{
+        plant: char::from_u32(*m.get(r, c).unwrap() as u32).unwrap(),
+        cells,
+        perimeter,
+        sides: boundaries.iter().map(|b| b.sides).sum(),
+        bbox,
+        holes,
+        boundaries,
+    }
 }
 
+// Splits a region's fence into closed loops and counts the sides of each as
+// the number of turns taken while walking it. Where two cells of the region
+// only touch at a corner the walk always turns right, so it stays on the cell
+// it was following and each loop is the boundary of a 4-connected piece.
+fn trace_boundaries(cells: &[(usize, usize)]) -> Vec<BoundaryLoop> {
+    let inside: HashSet<(usize, usize)> = cells.iter().copied().collect();
+    let is_in = |r: usize, c: usize, dr: i32, dc: i32| {
+        let n = (r as i32 + dr, c as i32 + dc);
+        n.0 >= 0 && n.1 >= 0 && inside.contains(&(n.0 as usize, n.1 as usize))
+    };
+
+    let mut edges: Vec<Edge> = Vec::<Edge>::new();
+    for &(r, c) in cells.iter() {
+        if !is_in(r, c, -1, 0) {
+            edges.push(Edge{from: (r, c), dir: 0});
+        }
+        if !is_in(r, c, 0, 1) {
+            edges.push(Edge{from: (r, c + 1), dir: 1});
+        }
+        if !is_in(r, c, 1, 0) {
+            edges.push(Edge{from: (r + 1, c + 1), dir: 2});
+        }
+        if !is_in(r, c, 0, -1) {
+            edges.push(Edge{from: (r + 1, c), dir: 3});
+        }
+    }
+
+    let by_start: HashMap<((usize, usize), usize), usize> = edges.iter().enumerate().map(|(i, e)| ((e.from, e.dir), i)).collect();
+    let mut used: Vec<bool> = vec![false; edges.len()];
+    let mut loops: Vec<BoundaryLoop> = Vec::<BoundaryLoop>::new();
+
+    for start in 0..edges.len() {
+        if used[start] {
+            continue;
+        }
+
+        let mut path: Vec<Edge> = Vec::<Edge>::new();
+        let mut cur = start;
+        while !used[cur] {
+            used[cur] = true;
+            path.push(edges[cur]);
+            let e = edges[cur];
+            cur = [(e.dir + 1) % 4, e.dir, (e.dir + 3) % 4].iter()
+                .find_map(|d| by_start.get(&(e.to(), *d)).copied())
+                .unwrap();
+        }
+
+        let sides = (0..path.len()).filter(|i| path[*i].dir != path[(i + 1) % path.len()].dir).count() as u32;
+        let area: i64 = path.iter().map(|e| {
+            let (a, b) = (e.from, e.to());
+            a.1 as i64 * b.0 as i64 - b.1 as i64 * a.0 as i64
+        }).sum();
+        loops.push(BoundaryLoop{edges: path, outer: area > 0, sides});
+    }
+
+    loops
+}
+
+// Counts the pockets of other plants fully enclosed by a region. Works on the
+// bounding box padded by one cell: everything outside the region that is
+// 8-connected to the padding is exterior, and each remaining 8-connected
+// component is a hole.
+fn count_holes(cells: &[(usize, usize)], bbox: ((usize, usize), (usize, usize))) -> u32 {
+    let rows = bbox.1.0 - bbox.0.0 + 3;
+    let cols = bbox.1.1 - bbox.0.1 + 3;
+    let mut grid = Matrix::new(rows, cols);
+    for p in cells.iter() {
+        *grid.get_mut(p.0 - bbox.0.0 + 1, p.1 - bbox.0.1 + 1).unwrap() = 1;
+    }
+
+    let mut holes: u32 = 0;
+    for r in 0..rows {
+        for c in 0..cols {
+            if *grid.get(r, c).unwrap() != 0 {
+                continue;
+            }
+            if r > 0 && c > 0 && r + 1 < rows && c + 1 < cols {
+                holes += 1;
+            }
+
+            let mut q: VecDeque<(usize, usize)> = VecDeque::<(usize, usize)>::new();
+            *grid.get_mut(r, c).unwrap() = 2;
+            q.push_front((r, c));
+            while let Some(p) = q.pop_back() {
+                for dr in -1..=1_i32 {
+                    for dc in -1..=1_i32 {
+                        let n = ((p.0 as i32 + dr) as usize, (p.1 as i32 + dc) as usize);
+                        if grid.get(n.0, n.1) == Some(&0) {
+                            *grid.get_mut(n.0, n.1).unwrap() = 2;
+                            q.push_front(n);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    holes
+}
+
+// Counts the corners of the region's fence at cell `p`, which add up to the
+// region's sides. Kept as an independent check on `trace_boundaries`.
+#[cfg(test)]
 fn count_corners(p: (usize, usize), m: &Matrix) -> u32 {
     let mut corners: u32 = 0;
     let k = *m.get(p.0, p.1).unwrap();
@@ -184,3 +408,72 @@ fn count_corners(p: (usize, usize), m: &Matrix) -> u32 {
 
     return corners;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LARGER: &str = "data/debug_12.txt";
+    const E_SHAPE: &str = "data/debug_12_2.txt";
+    const AB: &str = "data/debug_12_3.txt";
+
+    fn bulk_price(fname: &str) -> u32 {
+        total_price(&find_regions(&parse_input(fname)), |r| r.area() * r.sides)
+    }
+
+    #[test]
+    fn larger_example() {
+        let regions = find_regions(&parse_input(LARGER));
+        assert_eq!(total_price(&regions, |r| r.area() * r.perimeter), 1930);
+        assert_eq!(bulk_price(LARGER), 1206);
+    }
+
+    #[test]
+    fn e_shape() {
+        assert_eq!(bulk_price(E_SHAPE), 236);
+    }
+
+    #[test]
+    fn touching_corners() {
+        assert_eq!(bulk_price(AB), 368);
+    }
+
+    #[test]
+    fn one_outer_loop_per_region() {
+        for fname in [LARGER, E_SHAPE, AB] {
+            for region in find_regions(&parse_input(fname)) {
+                assert_eq!(region.boundaries.iter().filter(|b| b.outer).count(), 1);
+                assert_eq!(region.boundaries.iter().map(|b| b.edges.len() as u32).sum::<u32>(), region.perimeter);
+            }
+        }
+    }
+
+    #[test]
+    fn sides_match_corners_on_random_maps() {
+        let mut seed: u64 = 0x2024_1212;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        for _ in 0..500 {
+            let rows = 1 + (next() % 12) as usize;
+            let cols = 1 + (next() % 12) as usize;
+            let plants = 1 + next() % 3;
+
+            let mut m = Matrix::new(rows, cols);
+            for r in 0..rows {
+                for c in 0..cols {
+                    *m.get_mut(r, c).unwrap() = 'A' as i32 + (next() % plants) as i32;
+                }
+            }
+
+            for region in find_regions(&m) {
+                let corners: u32 = region.cells.iter().map(|p| count_corners(*p, &m)).sum();
+                assert_eq!(region.sides, corners);
+            }
+        }
+    }
+}
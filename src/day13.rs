@@ -1,232 +1,582 @@
 use std::fs::read_to_string;
 use regex::Regex;
 
+use aoc_2024::matrix::{Matrix, Rational, Scalar};
+
+const OFFSET: i64 = 10000000000000;
+
 #[derive(Debug, Clone)]
-struct Matrix {
-    rows: usize,
-    cols: usize,
-    data: Vec<f64>
+struct Button {
+    label: String,
+    delta: Vec<i64>,
+    cost: i64,
 }
 
-impl Matrix {
-    pub fn new(rows: usize, cols: usize) -> Self {
-            Matrix { rows: rows, cols: cols, data: vec![0.0; rows * cols] }
-    }
+// A claw machine with any number of buttons moving the claw through any
+// number of axes. Every button's `delta` lists the prize's axes in order.
+#[derive(Debug, Clone)]
+struct Machine {
+    buttons: Vec<Button>,
+    prize: Vec<i64>,
+}
 
-    pub fn get(&self, r: usize, c: usize) -> Option<&f64> {
-        if self.in_bounds(r, c) {
-            Some(&self.data[r * self.cols + c])
-        } else {
-            None
-        }
+impl Machine {
+    fn cost(&self, presses: &[i64]) -> i64 {
+        self.buttons.iter().zip(presses).map(|(b, n)| b.cost * n).sum()
     }
-    
-    pub fn get_mut(&mut self, r: usize, c: usize) -> Option<&mut f64> {
-        if self.in_bounds(r, c) {
-            Some(&mut self.data[r * self.cols + c])
-        } else {
-            None
-        }
+
+    // Cheapest presses per button that land exactly on the prize shifted by
+    // `offset` on every axis, or `None` if the prize can't be reached.
+    fn solve(&self, offset: i64) -> Option<Vec<i64>> {
+        let m: Matrix<i128> = Matrix::from_rows((0..self.prize.len())
+            .map(|r| self.buttons.iter().map(|b| b.delta[r] as i128).collect())
+            .collect());
+        let p: Vec<i128> = self.prize.iter().map(|v| (*v + offset) as i128).collect();
+        let c: Vec<i128> = self.buttons.iter().map(|b| b.cost as i128).collect();
+
+        let presses = solve_ilp(&m, &p, &c)?;
+        Some(presses.into_iter().map(|n| n as i64).collect())
     }
+}
 
-    pub fn in_bounds(&self, r: usize, c: usize) -> bool {
-        r < self.rows && c < self.cols
+// Minimises `c . x` subject to `m x = p` and `x >= 0` integer, for a small
+// integer matrix `m` (one row per axis, one column per button).
+//
+// A set of buttons forming a basis of the column space is solved exactly with
+// Cramer's rule in terms of the others. With no buttons left over the answer
+// is unique, as in the usual two-button machine. With one left over, say
+// pressed `t` times, every basis count is `(u - v t) / d`, so the valid `t`
+// form a few residue classes mod `d` within an interval and the linear cost is
+// minimised at an end of one of them; collinear two-button machines land here.
+//
+// With more buttons left over the search starts from an optimal vertex of the
+// LP relaxation. By the proximity theorem of Cook, Gerards, Schrijver and
+// Tardos some integer optimum lies within `n * delta` of it on every button,
+// `delta` being the largest minor of `m`. Buttons outside the vertex's basis
+// are pressed zero times there, so all but one of them are enumerated up to
+// that bound and the last is handled as above. The cost is exponential in the
+// number of extra buttons but independent of the size of the prize.
+fn solve_ilp(m: &Matrix<i128>, p: &[i128], c: &[i128]) -> Option<Vec<i128>> {
+    let n = c.len();
+    let (rows, cols) = basis(m);
+    let free: Vec<usize> = (0..n).filter(|j| !cols.contains(j)).collect();
+    if free.len() <= 1 {
+        return Cramer::new(m, &rows, &cols, free.first().copied()).solve(m, p, c);
     }
 
-    pub fn det(&self) -> f64 {
-        assert!(self.rows == 2);
-        assert!(self.cols == 2);
+    let mut cols = lp_vertex(m, p, c, &rows)?;
+    let free: Vec<usize> = (0..n).filter(|j| !cols.contains(j)).collect();
+    let (last, fixed) = free.split_last().unwrap();
+    let bound = n as i128 * max_minor(m, &rows);
+
+    // The basis plus the last free button, solved for each choice of presses
+    // of the `fixed` buttons.
+    let basis_cols: Vec<usize> = (0..cols.len()).collect();
+    cols.push(*last);
+    let all_rows: Vec<usize> = (0..m.rows).collect();
+    let sub_m = m.select(&all_rows, &cols);
+    let sub_c: Vec<i128> = cols.iter().map(|j| c[*j]).collect();
+    let cramer = Cramer::new(&sub_m, &rows, &basis_cols, Some(basis_cols.len()));
+
+    let mut best: Option<(i128, Vec<i128>)> = None;
+    let mut t: Vec<i128> = vec![0; fixed.len()];
+    loop {
+        let sub_p: Vec<i128> = (0..p.len())
+            .map(|r| p[r] - fixed.iter().zip(&t).map(|(f, k)| m.row(r)[*f] * k).sum::<i128>())
+            .collect();
+        if let Some(y) = cramer.solve(&sub_m, &sub_p, &sub_c) {
+            let mut x: Vec<i128> = vec![0; n];
+            for (j, v) in cols.iter().zip(&y) {
+                x[*j] = *v;
+            }
+            for (f, k) in fixed.iter().zip(&t) {
+                x[*f] = *k;
+            }
+            let cost = dot(c, &x);
+            if best.as_ref().is_none_or(|(b, _)| cost < *b) {
+                best = Some((cost, x));
+            }
+        }
 
-        (self.data[0] * self.data[3]) - (self.data[1] * self.data[2])
+        let Some(i) = t.iter().position(|k| *k < bound) else {
+            break;
+        };
+        t[i] += 1;
+        t[..i].iter_mut().for_each(|k| *k = 0);
     }
 
-    pub fn inv(&self) -> Matrix {
-        assert!(self.rows == 2);
-        assert!(self.cols == 2);
+    best.map(|(_, x)| x)
+}
 
-        let mut inverted: Matrix = Matrix::new(2, 2);
-        let d = self.det();
+// Basis of an optimal vertex of the LP relaxation `min c . x`, `m x = p`,
+// `x >= 0`, or `None` if it is infeasible or unbounded below. `rows` are
+// independent rows spanning those of `m`. With a handful of buttons both the
+// vertices and the extreme rays are few enough to enumerate.
+fn lp_vertex(m: &Matrix<i128>, p: &[i128], c: &[i128], rows: &[usize]) -> Option<Vec<usize>> {
+    let rank = rows.len();
+    let pr: Vec<i128> = rows.iter().map(|r| p[*r]).collect();
+    let mut best: Option<(Rational, Vec<usize>)> = None;
+    for cols in combinations(m.cols, rank) {
+        let b = m.select(rows, &cols);
+        let d = b.det();
+        if d == 0 {
+            continue;
+        }
+        let x: Vec<Rational> = (0..rank).map(|i| Rational::new(b.with_col(i, &pr).det(), d)).collect();
+        if x.iter().any(|v| *v < Rational::zero()) {
+            continue;
+        }
+        let cost = cols.iter().zip(&x).fold(Rational::zero(), |acc, (j, v)| acc + Rational::new(c[*j], 1) * *v);
+        if best.as_ref().is_none_or(|(b, _)| cost < *b) {
+            best = Some((cost, cols));
+        }
+    }
+    let (_, cols) = best?;
+
+    // Extreme rays of `{y >= 0 : m y = 0}` are kernel vectors on minimal
+    // column sets, given by the cofactors of `rank(set)` independent rows.
+    for size in 2..=rank + 1 {
+        for set in combinations(m.cols, size) {
+            for sel in combinations(rank, size - 1) {
+                let rs: Vec<usize> = sel.iter().map(|i| rows[*i]).collect();
+                let mut y: Vec<i128> = (0..size)
+                    .map(|k| {
+                        let rest: Vec<usize> = set.iter().enumerate().filter(|(i, _)| *i != k).map(|(_, j)| *j).collect();
+                        let minor = m.select(&rs, &rest).det();
+                        if k % 2 == 0 { minor } else { -minor }
+                    })
+                    .collect();
+                if y.iter().all(|v| *v == 0) || (0..m.rows).any(|r| set.iter().zip(&y).map(|(j, v)| m.row(r)[*j] * v).sum::<i128>() != 0) {
+                    continue;
+                }
+                if y.iter().all(|v| *v <= 0) {
+                    y.iter_mut().for_each(|v| *v = -*v);
+                }
+                if y.iter().all(|v| *v >= 0) && set.iter().zip(&y).map(|(j, v)| c[*j] * v).sum::<i128>() < 0 {
+                    return None;
+                }
+            }
+        }
+    }
 
-        inverted.data[0] = self.data[3] / d;
-        inverted.data[1] = -1.0 * (self.data[1] / d);
-        inverted.data[2] = -1.0 * (self.data[2] / d);
-        inverted.data[3] = self.data[0] / d;
+    Some(cols)
+}
 
-        return inverted;
+// Largest absolute value of any square minor of `m` restricted to `rows`,
+// and at least 1.
+fn max_minor(m: &Matrix<i128>, rows: &[usize]) -> i128 {
+    let mut best: i128 = 1;
+    for size in 1..=rows.len() {
+        for sel in combinations(rows.len(), size) {
+            let rs: Vec<usize> = sel.iter().map(|i| rows[*i]).collect();
+            for cols in combinations(m.cols, size) {
+                best = best.max(m.select(&rs, &cols).det().abs());
+            }
+        }
     }
+
+    best
 }
 
-impl std::ops::Mul<Matrix> for Matrix {
-    type Output = Matrix;
+// Every `k`-element subset of `0..n`, each in ascending order.
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![Vec::new()];
+    }
+    if k > n {
+        return Vec::new();
+    }
+
+    let mut out: Vec<Vec<usize>> = combinations(n - 1, k);
+    for mut set in combinations(n - 1, k - 1) {
+        set.push(n - 1);
+        out.push(set);
+    }
+    out
+}
 
-    fn mul(self, rhs: Matrix) -> Matrix {
-        assert!(self.cols == rhs.rows);
-        let mut res: Matrix = Matrix::new(self.rows, rhs.cols);
+// The cases `solve_ilp` handles directly: `cols` is a basis, independent on
+// `rows`, and `free` the one button outside it, if any. Pressing `free` `t`
+// times leaves basis button `i` at `(u[i] - v[i] t) / d`, where `u` depends
+// linearly on the prize through `adj`, so everything but `u` is computed once.
+struct Cramer {
+    rows: Vec<usize>,
+    cols: Vec<usize>,
+    free: Option<usize>,
+    d: i128,
+    adj: Vec<Vec<i128>>,
+    v: Vec<i128>,
+}
 
-        for i in 0..self.rows {
-            for j in 0..rhs.cols {
-                for k in 0..self.cols {
-                    *res.get_mut(i, j).unwrap() += *self.get(i, k).unwrap() * rhs.get(k, j).unwrap();
-                }
-            }
+impl Cramer {
+    fn new(m: &Matrix<i128>, rows: &[usize], cols: &[usize], free: Option<usize>) -> Self {
+        let b = m.select(rows, cols);
+        let unit = |k: usize| -> Vec<i128> { (0..rows.len()).map(|r| (r == k) as i128).collect() };
+        let mut d = b.det();
+        let mut adj: Vec<Vec<i128>> = (0..cols.len())
+            .map(|i| (0..rows.len()).map(|k| b.with_col(i, &unit(k)).det()).collect())
+            .collect();
+        let mut v: Vec<i128> = vec![0; cols.len()];
+        if let Some(f) = free {
+            let fr: Vec<i128> = rows.iter().map(|r| m.row(*r)[f]).collect();
+            v = (0..cols.len()).map(|i| b.with_col(i, &fr).det()).collect();
+        }
+        if d < 0 {
+            d = -d;
+            adj.iter_mut().flatten().for_each(|x| *x = -*x);
+            v.iter_mut().for_each(|x| *x = -*x);
         }
 
-        return res;
+        Cramer{rows: rows.to_vec(), cols: cols.to_vec(), free, d, adj, v}
     }
-}
-
-impl std::ops::Mul<&Matrix> for Matrix {
-    type Output = Matrix;
 
-    fn mul(self, rhs: &Matrix) -> Matrix {
-        assert!(self.cols == rhs.rows);
-        let mut res: Matrix = Matrix::new(self.rows, rhs.cols);
+    fn solve(&self, m: &Matrix<i128>, p: &[i128], c: &[i128]) -> Option<Vec<i128>> {
+        let (d, v) = (self.d, &self.v);
+        let u: Vec<i128> = self.adj.iter().map(|a| self.rows.iter().zip(a).map(|(r, x)| p[*r] * x).sum()).collect();
+
+        // Feasible range of `t` from `t >= 0` and every `u - v t >= 0`.
+        let mut lo: i128 = 0;
+        let mut hi: Option<i128> = if self.free.is_none() { Some(0) } else { None };
+        for i in 0..self.cols.len() {
+            match v[i].signum() {
+                0 if u[i] < 0 => return None,
+                1 => hi = Some(hi.map_or(div_floor(u[i], v[i]), |h| h.min(div_floor(u[i], v[i])))),
+                -1 => lo = lo.max(div_ceil(u[i], v[i])),
+                _ => {}
+            }
+        }
 
-        for i in 0..self.rows {
-            for j in 0..rhs.cols {
-                for k in 0..self.cols {
-                    *res.get_mut(i, j).unwrap() += *self.get(i, k).unwrap() * rhs.get(k, j).unwrap();
+        // The `t` giving whole presses form one residue class `r` mod `period`.
+        let (mut r, mut period) = (0, 1);
+        for i in 0..self.cols.len() {
+            let (b, n) = congruence(v[i], u[i], d)?;
+            (r, period) = crt(r, period, b, n)?;
+        }
+        let t0 = lo + (r - lo).rem_euclid(period);
+
+        // With no upper limit on `t` the minimum only exists if the cost
+        // doesn't fall as `t` grows, and then it is at the lowest `t`.
+        let t1 = match hi {
+            Some(h) => t0 + div_floor(h - t0, period) * period,
+            None => {
+                let f = self.free?;
+                let slope = c[f] * d - (0..self.cols.len()).map(|i| c[self.cols[i]] * v[i]).sum::<i128>();
+                if slope < 0 {
+                    return None;
                 }
+                t0
             }
+        };
+        if t1 < t0 {
+            return None;
         }
 
-        return res;
+        let at = |t: i128| -> Vec<i128> {
+            let mut x: Vec<i128> = vec![0; c.len()];
+            for (i, j) in self.cols.iter().enumerate() {
+                x[*j] = (u[i] - v[i] * t) / d;
+            }
+            if let Some(f) = self.free {
+                x[f] = t;
+            }
+            x
+        };
+        let (x0, x1) = (at(t0), at(t1));
+        let x = if dot(c, &x1) < dot(c, &x0) { x1 } else { x0 };
+
+        // Rows outside the basis only hold if the prize is consistent with them.
+        if (0..p.len()).all(|r| dot(m.row(r), &x) == p[r]) {
+            Some(x)
+        } else {
+            None
+        }
     }
 }
 
-impl std::ops::Add<Matrix> for Matrix {
-    type Output = Matrix;
-
-    fn add(self, rhs: Matrix) -> Matrix {
-        assert!(self.rows == rhs.rows);
-        assert!(self.cols == rhs.cols);
-        let mut res: Matrix = Matrix::new(self.rows, rhs.cols);
-        for i in 0..res.data.len() {
-            res.data[i] = self.data[i] + res.data[i];
-        }
+// Solutions of `a t = b (mod n)` as `t = r (mod period)`, or `None` if none.
+fn congruence(a: i128, b: i128, n: i128) -> Option<(i128, i128)> {
+    let (g, x, _) = ext_gcd(a.rem_euclid(n), n);
+    if b.rem_euclid(g) != 0 {
+        return None;
+    }
+    let period = n / g;
+    Some(((b / g).rem_euclid(period) * x.rem_euclid(period) % period, period))
+}
 
-        return res;
+// Combines `t = a (mod m)` and `t = b (mod n)` for moduli that need not be
+// coprime.
+fn crt(a: i128, m: i128, b: i128, n: i128) -> Option<(i128, i128)> {
+    let (g, x, _) = ext_gcd(m, n);
+    if (b - a) % g != 0 {
+        return None;
     }
+    let lcm = m / g * n;
+    let k = ((b - a) / g).rem_euclid(n / g) * x.rem_euclid(n / g) % (n / g);
+    Some(((a + m * k).rem_euclid(lcm), lcm))
 }
 
-impl std::ops::Add<&Matrix> for Matrix {
-    type Output = Matrix;
+// `(g, x, y)` with `a x + b y = g = gcd(a, b)`.
+fn ext_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = ext_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
 
-    fn add(self, rhs: &Matrix) -> Matrix {
-        assert!(self.rows == rhs.rows);
-        assert!(self.cols == rhs.cols);
-        let mut res: Matrix = Matrix::new(self.rows, rhs.cols);
-        for i in 0..res.data.len() {
-            res.data[i] = self.data[i] + res.data[i];
+// Picks columns forming a basis of the column space of `m`, and as many rows
+// on which those columns are independent.
+fn basis(m: &Matrix<i128>) -> (Vec<usize>, Vec<usize>) {
+    let all_rows: Vec<usize> = (0..m.rows).collect();
+    let mut cols: Vec<usize> = Vec::<usize>::new();
+    for j in 0..m.cols {
+        cols.push(j);
+        if m.select(&all_rows, &cols).rank() < cols.len() {
+            cols.pop();
         }
+    }
 
-        return res;
+    let mut rows: Vec<usize> = Vec::<usize>::new();
+    for r in 0..m.rows {
+        rows.push(r);
+        if m.select(&rows, &cols).rank() < rows.len() {
+            rows.pop();
+        }
     }
+
+    (rows, cols)
 }
 
-/*impl std::ops::Add<Matrix> for &Matrix {
-    type Output = Matrix;
+fn dot(a: &[i128], b: &[i128]) -> i128 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
 
-    fn add(&self, rhs: Matrix) -> Matrix {
-        assert!(self.rows == rhs.rows);
-        assert!(self.cols == rhs.cols);
-        let mut res: Matrix = Matrix::new(self.rows, rhs.cols);
-        for i in 0..res.data.len() {
-            res.data[i] = self.data[i] + res.data[i];
-        }
+fn div_floor(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) { q - 1 } else { q }
+}
 
-        return res;
+fn div_ceil(a: i128, b: i128) -> i128 {
+    -div_floor(-a, b)
+}
+
+fn main() {
+    let fname = "data/input_13.txt";
+    let data = match parse_input(fname) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("{}: {}", fname, e);
+            std::process::exit(1);
+        }
+    };
+
+    for (part, offset) in [(1, 0), (2, OFFSET)] {
+        let mut total = 0;
+        for (i, machine) in data.iter().enumerate() {
+            match machine.solve(offset) {
+                Some(presses) => {
+                    let counts: Vec<String> = machine.buttons.iter().zip(&presses).map(|(b, n)| format!("{}: {:?}", b.label, n)).collect();
+                    println!("Machine {:?}: {}, Cost: {:?}", i, counts.join(", "), machine.cost(&presses));
+                    total += machine.cost(&presses);
+                }
+                None => println!("Machine {:?}: No way to reach the prize", i),
+            }
+        }
+        println!("Part {}: {:?}", part, total);
     }
 }
 
-impl std::ops::Add<&Matrix> for &Matrix {
-    type Output = Matrix;
+// Parses machines such as
+//
+//     Button A: X+94, Y+34
+//     Button B: X+22, Y-67
+//     Prize: X=8400, Y=5400
+//
+// Any number of buttons and axes are accepted. Every button must move along
+// exactly the prize's axes, in any order. A button may end with `, Cost=N`;
+// otherwise A costs 3 tokens and every other button 1.
+fn parse_input(fname: &str) -> Result<Vec<Machine>, String> {
+    let text = read_to_string(fname).map_err(|e| e.to_string())?;
+    parse_machines(&text)
+}
 
-    fn add(&self, rhs: &Matrix) -> Matrix {
-        assert!(self.rows == rhs.rows);
-        assert!(self.cols == rhs.cols);
-        let mut res: Matrix = Matrix::new(self.rows, rhs.cols);
-        for i in 0..res.data.len() {
-            res.data[i] = self.data[i] + res.data[i];
-        }
+// Named axis offsets as read, e.g. `[("X", 94), ("Y", 34)]`.
+type Axes = Vec<(String, i64)>;
+
+fn parse_machines(text: &str) -> Result<Vec<Machine>, String> {
+    let mut data: Vec<Machine> = Vec::<Machine>::new();
+
+    let c1 = Regex::new(r"^Button (\w+): (.*)$").unwrap();
+    let c2 = Regex::new(r"^Prize: (.*)$").unwrap();
+    let field = Regex::new(r"([A-Za-z]+)[+=]?([+-]?[0-9]+)").unwrap();
+    let fields = |s: &str, line: usize| -> Result<Axes, String> {
+        field.captures_iter(s)
+            .map(|f| f[2].parse::<i64>().map(|v| (f[1].to_string(), v)).map_err(|e| format!("line {}: {}", line, e)))
+            .collect()
+    };
 
-        return res;
+    // Buttons of the machine being read, with their axes still by name.
+    let mut buttons: Vec<(Button, Axes, usize)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if let Some(caps) = c1.captures(line) {
+            let label = caps[1].to_string();
+            let mut button = Button{cost: if label == "A" { 3 } else { 1 }, label, delta: Vec::new()};
+            let mut axes: Axes = Vec::new();
+            for (name, v) in fields(&caps[2], i + 1)? {
+                if name == "Cost" {
+                    button.cost = v;
+                } else {
+                    axes.push((name, v));
+                }
+            }
+            buttons.push((button, axes, i + 1));
+        } else if let Some(caps) = c2.captures(line) {
+            let prize = fields(&caps[1], i + 1)?;
+            let mut machine = Machine{buttons: Vec::new(), prize: prize.iter().map(|(_, v)| *v).collect()};
+            for (mut button, axes, at) in buttons.drain(..) {
+                let named = |name: &str| axes.iter().find(|(a, _)| a == name).map(|(_, v)| *v);
+                if axes.len() != prize.len() || prize.iter().any(|(a, _)| named(a).is_none()) {
+                    let names = |v: &[(String, i64)]| v.iter().map(|(a, _)| a.as_str()).collect::<Vec<&str>>().join(", ");
+                    return Err(format!("line {}: button {} moves along {}, but the prize is on {}", at, button.label, names(&axes), names(&prize)));
+                }
+                button.delta = prize.iter().map(|(a, _)| named(a).unwrap()).collect();
+                machine.buttons.push(button);
+            }
+            data.push(machine);
+        } else if !line.trim().is_empty() {
+            return Err(format!("line {}: expected a button or a prize, found {:?}", i + 1, line));
+        }
+    }
+    if let Some((button, _, at)) = buttons.first() {
+        return Err(format!("line {}: button {} has no prize", at, button.label));
     }
-}*/
 
-fn main() {
-    let mut data = parse_input("data/input_13.txt");
+    Ok(data)
+}
 
-    let mut total = 0;
-    for (i, (x, y)) in data.iter().enumerate() {
-        let sol = x.inv() * y;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let A: f64 = *sol.get(0, 0).unwrap();
-        let B: f64 = *sol.get(1, 0).unwrap();
+    fn total(data: &[Machine], offset: i64) -> i64 {
+        data.iter().map(|m| m.solve(offset).map_or(0, |x| m.cost(&x))).sum()
+    }
 
-        if (A - A.round()).abs() > 0.01 {
-            println!("Machine {:?}: Can't push A fractional amount ({:?})", i, A);
-            continue;
-        }
-        
-        if (B - B.round()).abs() > 0.01 {
-            println!("Machine {:?}: Can't push B fractional amount ({:?})", i, B);
-            continue;
-        }
+    #[test]
+    fn example() {
+        let data = parse_input("data/debug_13.txt").unwrap();
+        assert_eq!(total(&data, 0), 480);
+        assert_eq!(total(&data, OFFSET), 875318608908);
+    }
 
-        println!("Machine {:?}: A: {:?}, B: {:?}, Cost: {:?}", i, A.round() as i32, B.round() as i32, (3.0 * A.round() + B.round()) as i32);
-        total += 3 * (A.round() as i32) + B.round() as i32;
+    #[test]
+    fn signed_deltas_and_axis_order() {
+        let data = parse_machines("Button A: Y+2, X-1\nButton B: X+3, Y+1\nPrize: X=5, Y=4\n").unwrap();
+        assert_eq!(data[0].buttons[0].delta, vec![-1, 2]);
+        assert_eq!(data[0].buttons[1].delta, vec![3, 1]);
+        assert_eq!(data[0].solve(0), Some(vec![1, 2]));
     }
-    println!("Part 1: {:?}", total);
-    
-    let mut total = 0;
-    for i in 0..data.len() {
-        *data[i].1.get_mut(0, 0).unwrap() += 10000000000000.0;
-        *data[i].1.get_mut(1, 0).unwrap() += 10000000000000.0;
-        let sol = data[i].0.inv() * data[i].1.clone();
-        let A: f64 = *sol.get(0, 0).unwrap();
-        let B: f64 = *sol.get(1, 0).unwrap();
 
-        if (A - A.round()).abs() > 0.01 {
-            println!("Machine {:?}: Can't push A fractional amount ({:?})", i, A);
-            continue;
-        }
-        
-        if (B - B.round()).abs() > 0.01 {
-            println!("Machine {:?}: Can't push B fractional amount ({:?})", i, B);
-            continue;
-        }
+    #[test]
+    fn mismatched_axes() {
+        assert!(parse_machines("Button A: X+1, Z+2\nButton B: X+3, Y+1\nPrize: X=5, Y=4\n").is_err());
+        assert!(parse_machines("Button A: X+1\nButton B: X+3, Y+1\nPrize: X=5, Y=4\n").is_err());
+        assert!(parse_machines("Button A: X+1, Y+1\n").is_err());
+    }
 
-        println!("Machine {:?}: A: {:?}, B: {:?}, Cost: {:?}", i, A.round() as i64, B.round() as i64, (3.0 * A.round() + B.round()) as i64);
-        total += 3 * (A.round() as i64) + B.round() as i64;
+    #[test]
+    fn collinear_buttons() {
+        // B is cheaper per unit of X, so use as many B presses as possible.
+        let data = parse_machines("Button A: X+2, Y+2\nButton B: X+3, Y+3\nPrize: X=13, Y=13\n").unwrap();
+        assert_eq!(data[0].solve(0), Some(vec![2, 3]));
     }
-    println!("Part 2: {:?}", total);
-}
 
-fn parse_input(fname: &str) -> Vec<(Matrix, Matrix)> {
-    let mut data: Vec<(Matrix, Matrix)> = Vec::<(Matrix, Matrix)>::new();
+    #[test]
+    fn free_button_without_positive_delta() {
+        // C only moves backwards, so pressing it never helps and nothing
+        // bounds it from above.
+        let data = parse_machines("Button A: X+1, Y+0\nButton B: X+0, Y+1\nButton C: X-1, Y-1\nPrize: X=4, Y=5\n").unwrap();
+        assert_eq!(data[0].solve(0), Some(vec![4, 5, 0]));
 
-    let c1 = Regex::new(r"^Button A: X\+([0-9]+), Y\+([0-9]+)").unwrap();
-    let c2 = Regex::new(r"^Button B: X\+([0-9]+), Y\+([0-9]+)").unwrap();
-    let c3 = Regex::new(r"^Prize: X=([0-9]+), Y=([0-9]+)").unwrap();
+        // Here C is free and pressing it more is cheaper, so no minimum exists.
+        let data = parse_machines("Button A: X+1, Y+0\nButton B: X+0, Y+1\nButton C: X-1, Y-1, Cost=-5\nPrize: X=4, Y=5\n").unwrap();
+        assert_eq!(data[0].solve(0), None);
+    }
 
-    data.push((Matrix::new(2, 2), Matrix::new(2, 1)));
-    let mut n: usize = 0;
-    for line in read_to_string(fname).unwrap().lines() {
-        if let Some(caps) = c1.captures(line) {
-            *data[n].0.get_mut(0, 0).unwrap() = caps[1].parse::<f64>().unwrap();
-            *data[n].0.get_mut(1, 0).unwrap() = caps[2].parse::<f64>().unwrap();
-        } else if let Some(caps) = c2.captures(line) {
-            *data[n].0.get_mut(0, 1).unwrap() = caps[1].parse::<f64>().unwrap();
-            *data[n].0.get_mut(1, 1).unwrap() = caps[2].parse::<f64>().unwrap();
-        } else if let Some(caps) = c3.captures(line) {
-            *data[n].1.get_mut(0, 0).unwrap() = caps[1].parse::<f64>().unwrap();
-            *data[n].1.get_mut(1, 0).unwrap() = caps[2].parse::<f64>().unwrap();
-        } else {
-            data.push((Matrix::new(2, 2), Matrix::new(2, 1)));
-            n += 1;
+    #[test]
+    fn several_redundant_buttons() {
+        let data = parse_machines("Button A: X+1, Y+1\nButton B: X+2, Y+2\nButton C: X+3, Y+3\nPrize: X=10, Y=10\n").unwrap();
+        assert_eq!(data[0].solve(0), Some(vec![0, 2, 2]));
+
+        // Two more buttons that duplicate A and B at a higher price change
+        // nothing, even with the prizes far away.
+        let mut data = parse_input("data/debug_13.txt").unwrap();
+        for machine in data.iter_mut() {
+            let (a, b) = (machine.buttons[0].clone(), machine.buttons[1].clone());
+            machine.buttons.push(Button{label: String::from("C"), cost: 4, ..a});
+            machine.buttons.push(Button{label: String::from("D"), cost: 2, ..b});
+        }
+        assert_eq!(total(&data, 0), 480);
+        assert_eq!(total(&data, OFFSET), 875318608908);
+    }
+
+    #[test]
+    fn unbounded_with_several_redundant_buttons() {
+        // C and D cancel out and pressing both gains 4 tokens.
+        let data = parse_machines("Button A: X+1, Y+0\nButton B: X+0, Y+1\nButton C: X+1, Y+1\nButton D: X-1, Y-1, Cost=-5\nPrize: X=4, Y=5\n").unwrap();
+        assert_eq!(data[0].solve(0), None);
+    }
+
+    // Cheapest presses found by trying every count that doesn't overshoot,
+    // for buttons with positive deltas only.
+    fn brute_force(m: &Machine, i: usize, rest: &[i64]) -> Option<i64> {
+        if i == m.buttons.len() {
+            return rest.iter().all(|v| *v == 0).then_some(0);
         }
+        let b = &m.buttons[i];
+        let mut best: Option<i64> = None;
+        let mut rest = rest.to_vec();
+        for k in 0.. {
+            if let Some(cost) = brute_force(m, i + 1, &rest) {
+                best = Some(best.map_or(cost + k * b.cost, |v| v.min(cost + k * b.cost)));
+            }
+            rest.iter_mut().zip(&b.delta).for_each(|(r, d)| *r -= d);
+            if rest.iter().any(|r| *r < 0) {
+                break;
+            }
+        }
+        best
     }
 
-    return data;
+    #[test]
+    fn matches_brute_force() {
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut rand = |n: u64| -> i64 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n) as i64
+        };
+
+        for _ in 0..100 {
+            let axes = 2 + rand(2) as usize;
+            let count = axes + 1 + rand(2) as usize;
+            let buttons: Vec<Button> = (0..count)
+                .map(|i| Button{label: i.to_string(), delta: (0..axes).map(|_| 1 + rand(6)).collect(), cost: 1 + rand(5)})
+                .collect();
+            let prize: Vec<i64> = (0..axes).map(|_| 20 + rand(40)).collect();
+            let machine = Machine{buttons, prize};
+
+            let expected = brute_force(&machine, 0, &machine.prize);
+            let found = machine.solve(0);
+            assert_eq!(found.as_ref().map(|x| machine.cost(x)), expected, "{:?}", machine);
+            if let Some(x) = found {
+                assert!(x.iter().all(|k| *k >= 0));
+                for (r, v) in machine.prize.iter().enumerate() {
+                    assert_eq!(machine.buttons.iter().zip(&x).map(|(b, k)| b.delta[r] * k).sum::<i64>(), *v);
+                }
+            }
+        }
+    }
 }
@@ -1,8 +1,7 @@
 use std::fs::read_to_string;
-use std::collections::VecDeque;
 use regex::Regex;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Robot {
     px: i32,
     py: i32,
@@ -10,7 +9,19 @@ struct Robot {
     vy: i32,
 }
 
-#[derive(Debug)]
+impl Robot {
+    // Position after `t` steps on a `width` by `height` torus. Each coordinate
+    // moves in a straight line modulo its side, so `t` is reduced first and the
+    // product can't overflow however large `t` is.
+    fn position_at(&self, t: i64, width: usize, height: usize) -> (i32, i32) {
+        let (w, h) = (width as i64, height as i64);
+        let x = (self.px as i64 + self.vx as i64 * t.rem_euclid(w)).rem_euclid(w);
+        let y = (self.py as i64 + self.vy as i64 * t.rem_euclid(h)).rem_euclid(h);
+        (x as i32, y as i32)
+    }
+}
+
+#[derive(Debug, Clone)]
 struct World {
     height: usize,
     width: usize,
@@ -18,18 +29,18 @@ struct World {
 }
 
 impl World {
-    pub fn step(&mut self) {
-        for r in self.robots.iter_mut() {
-            r.px = (r.px + r.vx) % (self.width as i32);
-            if r.px < 0 {
-                r.px = (self.width as i32) + r.px;
-            }
+    // The world `t` steps from now, computed directly rather than by stepping.
+    pub fn at(&self, t: i64) -> World {
+        let robots = self.robots.iter().map(|r| {
+            let (px, py) = r.position_at(t, self.width, self.height);
+            Robot{px, py, vx: r.vx, vy: r.vy}
+        }).collect();
 
-            r.py = (r.py + r.vy) % (self.height as i32);
-            if r.py < 0 {
-                r.py = (self.height as i32) + r.py;
-            }
-        }
+        World{height: self.height, width: self.width, robots}
+    }
+
+    pub fn quadrants_at(&self, t: i64) -> Vec<u32> {
+        self.at(t).count_partitions(2, 2, true)
     }
 
     pub fn print_map(&self) {
@@ -53,115 +64,160 @@ impl World {
         print!("\n\n");
     }
 
-    pub fn sum_quadrants(&self) -> Vec<u32>{
-        let mut res: Vec<u32> = vec![0, 0, 0, 0];
+    // Counts robots in a `cols` by `rows` grid of equal bands, row-major from
+    // the top left. With `exclude_midlines` the column (row) at every band
+    // boundary belongs to no band, which for a 2 by 2 grid on odd sides leaves
+    // out the middle lines as the puzzle's quadrants do.
+    pub fn count_partitions(&self, cols: usize, rows: usize, exclude_midlines: bool) -> Vec<u32> {
+        let mut res: Vec<u32> = vec![0; cols * rows];
 
         for r in self.robots.iter() {
-            if r.px < ((self.width / 2) as i32) {
-                if r.py < ((self.height / 2) as i32) {
-                    res[0] += 1;
-                } else if r.py > ((self.height / 2) as i32) {
-                    res[1] += 1;
-                }
-            } else if r.px > ((self.width / 2) as i32) {
-                if r.py < ((self.height / 2) as i32) {
-                    res[2] += 1;
-                } else if r.py > ((self.height / 2) as i32) {
-                    res[3] += 1;
-                }
-
+            let bx = band(r.px as usize, self.width, cols, exclude_midlines);
+            let by = band(r.py as usize, self.height, rows, exclude_midlines);
+            if let (Some(bx), Some(by)) = (bx, by) {
+                res[by * cols + bx] += 1;
             }
         }
 
-        return res;
+        res
     }
 
-    pub fn is_treelike(&self) -> bool {
-        let mut counts: Vec<u32> = vec![0; self.width * self.height];
-        for r in self.robots.iter() {
-            counts[(r.py as usize) * self.width + (r.px as usize)] += 1;
-            if counts[(r.py as usize) * self.width + (r.px as usize)] > 1 {
-                return false;
-            }
-        }
+    // Finds the first step at which the robots bunch up into a picture. The x
+    // coordinates repeat every `width` steps and the y coordinates every
+    // `height` steps, so the x spread is tightest at some `tx < width` and the
+    // y spread at some `ty < height`. The picture appears at the step matching
+    // both, found with the Chinese Remainder Theorem.
+    pub fn find_tree(&self) -> Option<i64> {
+        let mut best_x: (f64, i64) = (f64::MAX, 0);
+        let mut best_y: (f64, i64) = (f64::MAX, 0);
 
-        let mut v: Vec<u32> = vec![0; counts.len()];
-        let mut connected = 0;
-
-        for i in 0..counts.len() {
-            if counts[i] == 1 && v[i] == 0 {
-                connected += 1;
-
-                let mut q: VecDeque<usize> = VecDeque::<usize>::new();
-                q.push_front(i);
-
-                while q.len() > 0 {
-                    let c = q.pop_back().unwrap();
-
-                    if v[c] == 0 {
-                         v[c] = 1;
-     
-                         if c > self.width {
-                             q.push_front(c - self.width);
-                         }
-                         
-                         if c+self.width > counts.len() {
-                             q.push_front(c + self.width);
-                         }
-
-                         if c % self.width > 1 {
-                             q.push_front(c - 1);
-                         }
-                         
-                         if c % self.width < self.width - 1 {
-                             q.push_front(c + 1);
-                         }
-
-                    }
-                }
+        for t in 0..self.width.max(self.height) as i64 {
+            let world = self.at(t);
+            let vx = variance(world.robots.iter().map(|r| r.px as f64));
+            let vy = variance(world.robots.iter().map(|r| r.py as f64));
+            if t < self.width as i64 && vx < best_x.0 {
+                best_x = (vx, t);
+            }
+            if t < self.height as i64 && vy < best_y.0 {
+                best_y = (vy, t);
             }
         }
 
-        if connected > 1 {
-            println!("Found {:?} connected components", connected);
+        crt(best_x.1, self.width as i64, best_y.1, self.height as i64).map(|(t, _)| t)
+    }
+}
+
+// Band of `v` when `0..len` is cut into `parts` bands. The boundaries sit at
+// `i * len / parts`; when excluded those cells fall in no band.
+fn band(v: usize, len: usize, parts: usize, exclude: bool) -> Option<usize> {
+    if !exclude {
+        return Some(v * parts / len);
+    }
+
+    let mut k: usize = 0;
+    for i in 1..parts {
+        let edge = i * len / parts;
+        if v == edge {
+            return None;
+        }
+        if v > edge {
+            k += 1;
         }
-        return connected <= 1;
     }
+
+    Some(k)
+}
+
+// The example is 11 wide and 7 tall, the real puzzle 101 by 103. Without an
+// explicit size, the smallest of these that holds every robot is used, or the
+// robots' bounding box if neither does.
+const SIZES: [(usize, usize); 2] = [(11, 7), (101, 103)];
+
+fn infer_size(robots: &[Robot]) -> (usize, usize) {
+    let w = robots.iter().map(|r| r.px as usize + 1).max().unwrap_or(1);
+    let h = robots.iter().map(|r| r.py as usize + 1).max().unwrap_or(1);
+    SIZES.iter().copied().find(|(sw, sh)| w <= *sw && h <= *sh).unwrap_or((w, h))
+}
+
+fn variance(values: impl Iterator<Item = f64>) -> f64 {
+    let values: Vec<f64> = values.collect();
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / n
+}
+
+// Returns `(x, lcm)` with `x` the smallest non-negative solution of
+// `x = a1 (mod m1)` and `x = a2 (mod m2)`, or `None` if there is none.
+fn crt(a1: i64, m1: i64, a2: i64, m2: i64) -> Option<(i64, i64)> {
+    let (g, p, _) = ext_gcd(m1, m2);
+    if (a2 - a1) % g != 0 {
+        return None;
+    }
+
+    let lcm = m1 / g * m2;
+    let k = ((a2 - a1) / g) as i128 * p as i128 % (m2 / g) as i128;
+    let x = (a1 as i128 + k * m1 as i128).rem_euclid(lcm as i128);
+    Some((x as i64, lcm))
+}
+
+// Returns `(g, x, y)` with `a * x + b * y = g = gcd(a, b)`.
+fn ext_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = ext_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+const USAGE: &str = "Usage: day14 [input] [width height]";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
 }
 
 fn main() {
-    let robots = parse_input("data/input_14.txt");
-    let mut world: World = World{
-        height: 103,
-        width: 101,
-        robots: robots,
-    };
+    let args: Vec<String> = std::env::args().collect();
+    let fname = args.get(1).map_or("data/input_14.txt", |s| s.as_str());
+    let robots = parse_input(fname);
 
-    world.print_map();
-    for _ in 0..100 {
-        world.step();
+    let (width, height) = match (args.get(2), args.get(3)) {
+        (None, None) => infer_size(&robots),
+        (Some(w), Some(h)) => match (w.parse::<usize>(), h.parse::<usize>()) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+            _ => usage(),
+        },
+        _ => usage(),
+    };
+    if robots.iter().any(|r| r.px as usize >= width || r.py as usize >= height) {
+        eprintln!("{}x{} does not hold every robot's starting position", width, height);
+        usage();
     }
-    world.print_map();
-   
-    let mut prod: u32 = 1;
-    for v in world.sum_quadrants() {
-        prod *= v;
-    }
-    println!("Quadrants: {:?}, Safety Factor: {:?}", world.sum_quadrants(), prod);
-    
-    let robots = parse_input("data/input_14.txt");
-    let mut world: World = World{
-        height: 103,
-        width: 101,
-        robots: robots,
+    println!("World: {:?}x{:?}", width, height);
+
+    let world: World = World{
+        height,
+        width,
+        robots,
     };
 
-    for i in 0..10000 {
-        println!("Steps {:?}:", i);
-        world.print_map();
-        world.step();
+    world.print_map();
+    world.at(100).print_map();
+
+    for t in [100, 1_000_000_000_000_000] {
+        let quadrants = world.quadrants_at(t);
+        let prod: u64 = quadrants.iter().map(|v| *v as u64).product();
+        println!("Steps: {:?}, Quadrants: {:?}, Safety Factor: {:?}", t, quadrants, prod);
     }
 
+    match world.find_tree() {
+        Some(t) => {
+            println!("Steps {:?}:", t);
+            world.at(t).print_map();
+        }
+        None => println!("No step lines up the tightest rows and columns"),
+    }
 }
 
 fn parse_input(fname: &str) -> Vec<Robot> {
@@ -183,3 +239,72 @@ fn parse_input(fname: &str) -> Vec<Robot> {
 
     return robots;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn robot(px: i32, py: i32) -> Robot {
+        Robot{px, py, vx: 0, vy: 0}
+    }
+
+    #[test]
+    fn example_safety_factor() {
+        let robots = parse_input("data/debug_14.txt");
+        let (width, height) = infer_size(&robots);
+        assert_eq!((width, height), (11, 7));
+        let world = World{height, width, robots};
+        assert_eq!(world.quadrants_at(100).iter().product::<u32>(), 12);
+    }
+
+    #[test]
+    fn bands() {
+        assert_eq!((0..10).map(|v| band(v, 10, 3, false)).collect::<Vec<_>>(),
+            [0, 0, 0, 0, 1, 1, 1, 2, 2, 2].map(Some));
+        assert_eq!(band(4, 11, 2, true), Some(0));
+        assert_eq!(band(5, 11, 2, true), None);
+        assert_eq!(band(6, 11, 2, true), Some(1));
+        assert_eq!(band(3, 7, 2, true), None);
+        assert_eq!(band(3, 9, 3, true), None);
+        assert_eq!(band(4, 9, 3, true), Some(1));
+    }
+
+    #[test]
+    fn partitions() {
+        let robots = vec![robot(0, 0), robot(5, 3), robot(10, 6), robot(6, 0)];
+        let world = World{height: 7, width: 11, robots};
+        assert_eq!(world.count_partitions(2, 2, true), vec![1, 1, 0, 1]);
+        assert_eq!(world.count_partitions(2, 2, false), vec![2, 1, 0, 1]);
+        assert_eq!(world.count_partitions(1, 1, false), vec![4]);
+        assert_eq!(world.count_partitions(11, 1, true).iter().sum::<u32>(), 4 - 3);
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(infer_size(&[robot(10, 6), robot(0, 0)]), (11, 7));
+        assert_eq!(infer_size(&[robot(11, 0)]), (101, 103));
+        assert_eq!(infer_size(&[robot(200, 3)]), (201, 4));
+        assert_eq!(infer_size(&[]), (11, 7));
+    }
+
+    #[test]
+    fn chinese_remainders() {
+        assert_eq!(crt(2, 3, 3, 5), Some((8, 15)));
+        assert_eq!(crt(1, 4, 3, 6), Some((9, 12)));
+        assert_eq!(crt(0, 4, 1, 6), None);
+        let (x, lcm) = crt(50, 101, 60, 103).unwrap();
+        assert_eq!((x % 101, x % 103, lcm), (50, 60, 10403));
+        assert!(x < lcm);
+    }
+
+    #[test]
+    fn far_positions() {
+        let r = Robot{px: 2, py: 4, vx: 2, vy: -3};
+        assert_eq!(r.position_at(5, 11, 7), (1, 3));
+        let t: i64 = 1_000_000_000_000_000_000;
+        assert_eq!(r.position_at(t, 11, 7), r.position_at(t % 77, 11, 7));
+        let r = Robot{px: 0, py: 0, vx: i32::MAX, vy: i32::MIN};
+        let expected = ((i32::MAX as i128 * t as i128).rem_euclid(101) as i32, (i32::MIN as i128 * t as i128).rem_euclid(103) as i32);
+        assert_eq!(r.position_at(t, 101, 103), expected);
+    }
+}
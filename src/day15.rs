@@ -1,11 +1,31 @@
 use std::fs::read_to_string;
 use std::collections::VecDeque;
+#[cfg(feature = "tui")]
+use colored::Colorize;
 
+// A crate of any shape. `cells` are kept in reading order, so the first is the
+// anchor its GPS coordinate is taken from, and `glyphs` holds the character
+// drawn on each.
+#[derive(Clone, PartialEq)]
+struct Object {
+    cells: Vec<usize>,
+    glyphs: Vec<char>,
+}
+
+impl Object {
+    fn anchor(&self) -> usize {
+        self.cells[0]
+    }
+}
+
+#[derive(Clone, PartialEq)]
 struct World {
     width: usize,
     height: usize,
     map: Vec<char>,
     robot: (usize, usize),
+    objects: Vec<Object>,
+    owner: Vec<Option<usize>>,
 }
 
 impl World {
@@ -14,104 +34,188 @@ impl World {
             width, height,
             map: vec!['.'; width * height],
             robot: (0, 0),
+            objects: Vec::new(),
+            owner: vec![None; width * height],
         };
 
         return w;
     }
 
-    fn cmd_robot(&mut self, cmd: u8) {
+    fn cmd_robot(&mut self, cmd: u8) -> Event {
+        assert!(cmd < 4, "unknown command {}", cmd);
+        let from = self.robot;
+        let mut outcome = Outcome::Blocked;
         if let Some(src) = self.step(self.robot.0 * self.width + self.robot.1, cmd) {
             if let Some(crates) = self.find_crates(src, cmd) {
-                let mut chars: Vec<char> = vec!['.'; crates.len()];
-                for i in 0..crates.len() {
-                    chars[i] = self.map[crates[i]];
-                    self.map[crates[i]] = '.';
-                }
+                self.shift(&crates, cmd);
+                self.move_robot(src);
+                outcome = if crates.is_empty() { Outcome::Moved } else { Outcome::Pushed(crates) };
+            }
+        }
 
-                for i in 0..crates.len() {
-                    let n = self.step(crates[i], cmd).unwrap();
-                    self.map[n] = chars[i];
-                }
+        Event{cmd, from, outcome}
+    }
+
+    // Reverts the move recorded by `event`, which must be the last one applied.
+    fn undo(&mut self, event: &Event) {
+        // The robot stands on the first crate's old cell, so it backs off
+        // before the crates are pulled after it.
+        if event.outcome != Outcome::Blocked {
+            self.move_robot(event.from.0 * self.width + event.from.1);
+        }
+        if let Outcome::Pushed(ids) = &event.outcome {
+            self.shift(ids, (event.cmd + 2) % 4);
+        }
+    }
 
-                self.map[self.robot.0 * self.width + self.robot.1] = '.';
-                self.robot.0 = src / self.width;
-                self.robot.1 = src % self.width;
-                self.map[self.robot.0 * self.width + self.robot.1] = '@';
+    // Moves every object in `ids` one step in direction `cmd`.
+    fn shift(&mut self, ids: &[usize], cmd: u8) {
+        for id in ids.iter() {
+            for c in self.objects[*id].cells.iter() {
+                self.map[*c] = '.';
+                self.owner[*c] = None;
             }
         }
-   }
+        for id in ids.iter() {
+            let cells: Vec<usize> = self.objects[*id].cells.iter().map(|c| self.step(*c, cmd).unwrap()).collect();
+            for (c, g) in cells.iter().zip(self.objects[*id].glyphs.iter()) {
+                self.map[*c] = *g;
+                self.owner[*c] = Some(*id);
+            }
+            self.objects[*id].cells = cells;
+        }
+    }
+
+    fn move_robot(&mut self, to: usize) {
+        self.map[self.robot.0 * self.width + self.robot.1] = '.';
+        self.robot.0 = to / self.width;
+        self.robot.1 = to % self.width;
+        self.map[to] = '@';
+    }
     
 
+    // The cell next to `idx` in direction `cmd`, or `None` off the edge of the
+    // map.
     fn step(&self, idx: usize, cmd: u8) -> Option<usize> {
+        let (r, c) = (idx / self.width, idx % self.width);
         match cmd {
-            0 => Some(idx - self.width),
-            1 => Some(idx + 1),
-            2 => Some(idx + self.width),
-            3 => Some(idx - 1),
+            0 if r > 0 => idx.checked_sub(self.width),
+            1 if c + 1 < self.width => idx.checked_add(1),
+            2 if r + 1 < self.height => idx.checked_add(self.width),
+            3 if c > 0 => idx.checked_sub(1),
             _ => None,
         }
     }
 
+    // Objects that have to move for something to enter `idx` in direction
+    // `cmd`, or `None` if any of them would run into a wall or into the robot,
+    // as a crate wrapped around it would.
     fn find_crates(&self, idx: usize, cmd: u8) -> Option<Vec<usize>> {
-        let mut crates: Vec<usize> = Vec::<usize>::new();
-        let mut v: Vec<bool> = vec![false; self.map.len()];
+        let robot = self.robot.0 * self.width + self.robot.1;
+        let mut ids: Vec<usize> = Vec::<usize>::new();
+        let mut seen: Vec<bool> = vec![false; self.objects.len()];
         let mut q: VecDeque<usize> = VecDeque::<usize>::new();
         q.push_front(idx);
 
         while let Some(i) = q.pop_back() {
-            if !v[i] {
-                v[i] = true;
-                match self.map[i] {
-                    'O' => {
-                        crates.push(i);
-                        q.push_front(self.step(i, cmd)?);
-                    },
-                    '[' => {
-                        crates.push(i);
-                        q.push_front(self.step(i, cmd)?);
-                        q.push_front(self.step(i, 1)?);
-                    },
-                    ']' => {
-                        crates.push(i);
-                        q.push_front(self.step(i, cmd)?);
-                        q.push_front(self.step(i, 3)?);
-                    },
-                    '.' => (),
-                    '#' => return None,
-                     _  => (),
-                };
+            if self.map[i] == '#' || i == robot {
+                return None;
+            }
+            if let Some(id) = self.owner[i] {
+                if !seen[id] {
+                    seen[id] = true;
+                    ids.push(id);
+                    for c in self.objects[id].cells.iter() {
+                        let n = self.step(*c, cmd)?;
+                        if self.owner[n] != Some(id) {
+                            q.push_front(n);
+                        }
+                    }
+                }
             }
         }
-    
-        return Some(crates);
+
+        Some(ids)
     }
 
-    fn stretch(&self) -> Self {
-        let mut stretched: Self = Self::new(self.width * 2, self.height);
-        for i in 0..self.height {
-            for j in 0..self.width {
-                let idx: usize = i * self.width + j;
-                match self.map[idx] {
-                    '.' | '#' => {
-                        stretched.map[2 * idx] = self.map[idx];
-                        stretched.map[2 * idx + 1] = self.map[idx];
-                    },
-                    'O' => {
-                        stretched.map[2 * idx] = '[';
-                        stretched.map[2 * idx + 1] = ']';
-                    },
-                    '@' => {
-                        stretched.map[2 * idx] = '@';
-                        stretched.map[2 * idx + 1] = '.';
-                    },
-                     _  => (),
-                }
+    // Groups the crate characters of the map into objects. `O` is a crate of
+    // its own, `[` through `]` on one row (with `-` between) is one crate, and
+    // any other capital letter joins its orthogonal neighbours of the same
+    // letter into one crate.
+    fn index_objects(&mut self) {
+        self.objects.clear();
+        self.owner = vec![None; self.map.len()];
+
+        for i in 0..self.map.len() {
+            if self.owner[i].is_some() {
+                continue;
+            }
+
+            let mut cells: Vec<usize> = Vec::<usize>::new();
+            match self.map[i] {
+                'O' => cells.push(i),
+                '[' => {
+                    let mut j = i;
+                    while j % self.width + 1 < self.width && self.map[j] != ']' {
+                        cells.push(j);
+                        j += 1;
+                    }
+                    cells.push(j);
+                },
+                c if c.is_ascii_uppercase() => {
+                    let mut q: VecDeque<usize> = VecDeque::<usize>::new();
+                    let mut seen: Vec<bool> = vec![false; self.map.len()];
+                    q.push_front(i);
+                    seen[i] = true;
+                    while let Some(j) = q.pop_back() {
+                        cells.push(j);
+                        for cmd in 0..4 {
+                            if let Some(n) = self.step(j, cmd) {
+                                if !seen[n] && self.map[n] == c {
+                                    seen[n] = true;
+                                    q.push_front(n);
+                                }
+                            }
+                        }
+                    }
+                    cells.sort();
+                },
+                _ => continue,
+            }
+
+            let id = self.objects.len();
+            for c in cells.iter() {
+                self.owner[*c] = Some(id);
+            }
+            let glyphs = cells.iter().map(|c| self.map[*c]).collect();
+            self.objects.push(Object{cells, glyphs});
+        }
+    }
+
+    // Widens every cell `factor` times. Crates stay whole: `O` becomes `[-]`
+    // (or `[]` when doubled), bracket pieces grow `-` towards the inside, and
+    // lettered crates repeat their letter.
+    fn stretch(&self, factor: usize) -> Self {
+        assert!(factor > 0, "stretch factor must be positive");
+        let mut stretched: Self = Self::new(self.width * factor, self.height);
+        for (idx, c) in self.map.iter().enumerate() {
+            let cells: String = match c {
+                'O' if factor == 1 => String::from("O"),
+                'O' => format!("[{}]", "-".repeat(factor - 2)),
+                '[' => format!("[{}", "-".repeat(factor - 1)),
+                ']' => format!("{}]", "-".repeat(factor - 1)),
+                '@' => format!("@{}", ".".repeat(factor - 1)),
+                 _  => c.to_string().repeat(factor),
+            };
+            for (k, s) in cells.chars().enumerate() {
+                stretched.map[factor * idx + k] = s;
             }
         }
         stretched.robot.0 = self.robot.0;
-        stretched.robot.1 = 2 * self.robot.1;
+        stretched.robot.1 = factor * self.robot.1;
+        stretched.index_objects();
 
-        return stretched;
+        stretched
     }
     
     fn print_map(&self) {
@@ -125,104 +229,395 @@ impl World {
         println!("");
     }
 
+    // The map one line per row, coloured for the terminal.
+    #[cfg(feature = "tui")]
+    fn render(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::<String>::new();
+        for i in 0..self.height {
+            let mut line = String::new();
+            for idx in i * self.width..(i + 1) * self.width {
+                let cell = self.map[idx].to_string();
+                let cell = match self.map[idx] {
+                    _ if self.owner[idx].is_some() => cell.yellow(),
+                    '#' => cell.bright_black(),
+                    '@' => cell.bright_green().bold(),
+                     _  => cell.dimmed(),
+                };
+                line.push_str(&cell.to_string());
+            }
+            lines.push(line);
+        }
+
+        lines
+    }
+
     fn score_map(&self) -> i64 {
-        let mut total: i64 = 0;
+        self.objects.iter()
+            .map(|o| (100 * (o.anchor() / self.width) + o.anchor() % self.width) as i64)
+            .sum()
+    }
+}
 
-        for i in 0..self.height {
-            for j in 0..self.width {
-                total += match self.map[i * self.width + j] {
-                    'O' | '[' => (100 * i + j) as i64,
-                     _  => 0,
-                }
+#[derive(Debug, Clone, PartialEq)]
+enum Outcome {
+    Moved,
+    // IDs of the objects pushed.
+    Pushed(Vec<usize>),
+    Blocked,
+}
+
+#[derive(Debug, Clone)]
+struct Event {
+    cmd: u8,
+    from: (usize, usize),
+    outcome: Outcome,
+}
+
+impl Event {
+    // `v` for a plain move, `v3` for one pushing three crates, `v#` when
+    // blocked by a wall.
+    fn token(&self) -> String {
+        let dir = cmd_char(self.cmd);
+        match &self.outcome {
+            Outcome::Moved => format!("{}", dir),
+            Outcome::Pushed(crates) => format!("{}{}", dir, crates.len()),
+            Outcome::Blocked => format!("{}#", dir),
+        }
+    }
+}
+
+// Runs a move list against a world, keeping every event so that the world can
+// be wound back and forth to any step.
+struct Simulation {
+    world: World,
+    moves: Vec<u8>,
+    events: Vec<Event>,
+    // Steps at which a move was inserted into `moves` rather than loaded.
+    inserted: Vec<usize>,
+}
+
+impl Simulation {
+    fn new(world: World, moves: Vec<u8>) -> Self {
+        Simulation{world, moves, events: Vec::new(), inserted: Vec::new()}
+    }
+
+    // Number of moves applied so far.
+    fn step(&self) -> usize {
+        self.events.len()
+    }
+
+    fn forward(&mut self) -> Option<&Event> {
+        let cmd = *self.moves.get(self.events.len())?;
+        let event = self.world.cmd_robot(cmd);
+        self.events.push(event);
+        self.events.last()
+    }
+
+    // Applies `cmd` now, ahead of the rest of the move list. Undoing it takes
+    // it out of the list again.
+    #[cfg(feature = "tui")]
+    fn insert_move(&mut self, cmd: u8) -> Option<&Event> {
+        self.moves.insert(self.events.len(), cmd);
+        self.inserted.push(self.events.len());
+        self.forward()
+    }
+
+    fn undo(&mut self) -> Option<Event> {
+        let event = self.events.pop()?;
+        self.world.undo(&event);
+        if self.inserted.last() == Some(&self.events.len()) {
+            self.inserted.pop();
+            self.moves.remove(self.events.len());
+        }
+        Some(event)
+    }
+
+    // Replays or undoes moves until exactly `step` have been applied, or as
+    // many as the move list holds.
+    fn seek(&mut self, step: usize) {
+        while self.step() > step {
+            self.undo();
+        }
+        while self.step() < step && self.forward().is_some() {}
+    }
+
+    // The event tokens with runs of identical ones collapsed, e.g. `<x3 ^2 v#`.
+    fn log(&self) -> String {
+        let mut out: Vec<String> = Vec::<String>::new();
+        let mut i = 0;
+        while i < self.events.len() {
+            let token = self.events[i].token();
+            let mut n = 1;
+            while i + n < self.events.len() && self.events[i + n].token() == token {
+                n += 1;
             }
+            out.push(if n > 1 { format!("{}x{}", token, n) } else { token });
+            i += n;
         }
 
-        return total;
+        out.join(" ")
+    }
+
+    fn print_summary(&self) {
+        let pushes = self.events.iter().filter(|e| matches!(e.outcome, Outcome::Pushed(_))).count();
+        let blocked = self.events.iter().filter(|e| e.outcome == Outcome::Blocked).count();
+        println!("Moves: {:?}, Pushes: {:?}, Blocked: {:?}", self.step(), pushes, blocked);
+    }
+}
+
+fn cmd_char(cmd: u8) -> char {
+    match cmd {
+        0 => '^',
+        1 => '>',
+        2 => 'v',
+        3 => '<',
+        _ => '#',
     }
 }
 
+// Usage: day15 [input] [--tui] [--stretch=N]
 fn main() {
-    let (mut w, m) = parse_input("data/input_15.txt");
-    println!("Initial State:");
-    w.print_map();
-
-    for cmd in m {
-        w.cmd_robot(cmd);
-        println!("Move {}:", match cmd {
-            0 => "^",
-            1 => ">",
-            2 => "v",
-            3 => "<",
-            _ => "#",
-        });
-        w.print_map();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let fname = args.iter().find(|a| !a.starts_with("--")).map_or("data/input_15.txt", |s| s.as_str());
+    let factor = match args.iter().find_map(|a| a.strip_prefix("--stretch=")).map_or(Ok(2), |n| n.parse::<usize>()) {
+        Ok(n) if n > 0 => n,
+        _ => {
+            eprintln!("--stretch needs a positive whole number");
+            std::process::exit(2);
+        }
+    };
+    let (w, m) = match parse_input(fname) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}: {}", fname, e);
+            std::process::exit(1);
+        }
+    };
+
+    if args.iter().any(|a| a == "--tui") {
+        #[cfg(feature = "tui")]
+        interactive(w, m, factor).unwrap();
+        #[cfg(not(feature = "tui"))]
+        {
+            eprintln!("Interactive mode needs the `tui` feature");
+            std::process::exit(2);
+        }
+        #[cfg(feature = "tui")]
+        return;
     }
-    
-    let (w, m) = parse_input("data/input_15.txt");
-    let mut w = w.stretch();
-    println!("Initial State:");
-    w.print_map();
-
-    for cmd in m {
-        w.cmd_robot(cmd);
-        println!("Move {}:", match cmd {
-            0 => "^",
-            1 => ">",
-            2 => "v",
-            3 => "<",
-            _ => "#",
-        });
-        w.print_map();
+
+    let stretched = w.stretch(factor);
+
+    for (part, world) in [(1, w), (2, stretched)] {
+        let mut sim = Simulation::new(world, m.clone());
+        println!("Part {} Initial State:", part);
+        sim.world.print_map();
+
+        sim.seek(m.len());
+        println!("Log: {}", sim.log());
+        sim.print_summary();
+        sim.world.print_map();
+        println!("Part {}: {:?}", part, sim.world.score_map());
+        println!();
     }
 }
 
-fn parse_input(fname: &str) -> (World, Vec<u8>) {
-    let mut rows: usize = 0;
-    let mut cols: usize = 0;
+// Drives the robot from the keyboard. Arrows or WASD make a move of their own,
+// `n` or space plays the next move of the loaded list and `u` or backspace
+// undoes the last one. Tab switches between the normal and stretched
+// warehouses, each keeping its own history.
+#[cfg(feature = "tui")]
+fn interactive(world: World, moves: Vec<u8>, factor: usize) -> std::io::Result<()> {
+    use std::io::Write;
+    use crossterm::{cursor, execute, terminal};
+
+    let stretched = world.stretch(factor);
+    let mut sims = [Simulation::new(world, moves.clone()), Simulation::new(stretched, moves)];
+
+    let mut out = std::io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = interactive_loop(&mut sims, &mut out);
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    out.flush()?;
+
+    result
+}
+
+#[cfg(feature = "tui")]
+fn interactive_loop(sims: &mut [Simulation; 2], out: &mut std::io::Stdout) -> std::io::Result<()> {
+    use std::io::Write;
+    use crossterm::{cursor, queue, terminal};
+    use crossterm::event::{self, KeyCode, KeyEventKind, KeyModifiers};
 
-    for line in read_to_string(fname).unwrap().lines() {
-        if line.len() > 0 {
-            rows += 1;
-            cols = line.len();
-        } else {
-            break;
+    let mut active: usize = 0;
+    loop {
+        let sim = &mut sims[active];
+        queue!(out, terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0))?;
+        for line in sim.world.render() {
+            write!(out, "{}\r\n", line)?;
+        }
+        let last = sim.events.last().map_or(String::from("-"), |e| e.token());
+        write!(out, "\r\n{}, Move: {:?}/{:?}, Last: {}, GPS: {:?}\r\n",
+            if active == 0 { "Normal" } else { "Stretched" }, sim.step(), sim.moves.len(), last, sim.world.score_map())?;
+        write!(out, "arrows/wasd: move, n/space: next loaded move, u/backspace: undo, tab: switch width, q: quit\r\n")?;
+        out.flush()?;
+
+        let event::Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Up | KeyCode::Char('w') => { sim.insert_move(0); },
+            KeyCode::Right | KeyCode::Char('d') => { sim.insert_move(1); },
+            KeyCode::Down | KeyCode::Char('s') => { sim.insert_move(2); },
+            KeyCode::Left | KeyCode::Char('a') => { sim.insert_move(3); },
+            KeyCode::Char('n') | KeyCode::Char(' ') => { sim.forward(); },
+            KeyCode::Char('u') | KeyCode::Backspace => { sim.undo(); },
+            KeyCode::Tab => active = 1 - active,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            _ => (),
         }
     }
+}
+
+// Reads the map, up to the first blank line, and then the moves. The map must
+// be rectangular, walled all round and hold exactly one robot. Errors give the
+// 1-based line and column of the offending character.
+fn parse_input(fname: &str) -> Result<(World, Vec<u8>), String> {
+    let text = read_to_string(fname).map_err(|e| e.to_string())?;
+    let lines: Vec<&str> = text.lines().collect();
+    let rows = lines.iter().position(|l| l.is_empty()).unwrap_or(lines.len());
+    if rows == 0 {
+        return Err(String::from("line 1: missing map"));
+    }
+    let cols = lines[0].chars().count();
 
     let mut world = World::new(cols, rows);
+    let mut robots: Vec<(usize, usize)> = Vec::new();
+
+    for (i, line) in lines[..rows].iter().enumerate() {
+        if line.chars().count() != cols {
+            return Err(format!("line {}: map row is {} wide, expected {}", i + 1, line.chars().count(), cols));
+        }
+
+        let mut in_crate = false;
+        for (j, c) in line.chars().enumerate() {
+            let edge = i == 0 || j == 0 || i + 1 == rows || j + 1 == cols;
+            if edge && c != '#' {
+                return Err(format!("line {}, column {}: map edge must be '#', found {:?}", i + 1, j + 1, c));
+            }
+
+            match c {
+                '#' | '.' | 'O' => (),
+                '@' => robots.push((i, j)),
+                '[' if !in_crate => in_crate = true,
+                '-' | ']' if in_crate => in_crate = c == '-',
+                c if c.is_ascii_uppercase() => (),
+                _ => return Err(format!("line {}, column {}: unexpected {:?} in map", i + 1, j + 1, c)),
+            }
+            if in_crate && c != '[' && c != '-' {
+                return Err(format!("line {}, column {}: crate is missing its ']'", i + 1, j + 1));
+            }
+            world.map[i * cols + j] = c;
+        }
+    }
+
+    match robots.as_slice() {
+        [robot] => world.robot = *robot,
+        [] => return Err(String::from("map has no robot '@'")),
+        [_, (i, j), ..] => return Err(format!("line {}, column {}: second robot '@'", i + 1, j + 1)),
+    }
+
     let mut moves = Vec::<u8>::new();
+    for (i, line) in lines.iter().enumerate().skip(rows) {
+        for (j, c) in line.chars().enumerate() {
+            moves.push(match c {
+                '^' => 0,
+                '>' => 1,
+                'v' => 2,
+                '<' => 3,
+                _ => return Err(format!("line {}, column {}: invalid move {:?}", i + 1, j + 1, c)),
+            });
+        }
+    }
 
-    for (i, line) in read_to_string(fname).unwrap().lines().enumerate() {
-        if let Some(f) = line.chars().nth(0) {
-            match f {
-                '#' => {
-                    for (j, c) in line.chars().enumerate() {
-                        match c {
-                            '#' | 'O' => world.map[i * world.width + j] = c,
-                            '@' => {
-                                world.map[i * world.width + j] = c;
-                                world.robot.0 = i;
-                                world.robot.1 = j;
-                            },
-                            _ => (),
-                        };
-                    }
-                },
-                '^' | '>' | 'v' | '<' => {
-                    for m in line.chars() {
-                        match m {
-                            '^' => moves.push(0),
-                            '>' => moves.push(1),
-                            'v' => moves.push(2),
-                            '<' => moves.push(3),
-                            _ => (),
-                        }
-                    }
-                },
-                _ => (),
+    world.index_objects();
+
+    Ok((world, moves))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_to_start_and_replay_to_end() {
+        for fname in ["data/debug_15.txt", "data/debug_15_2.txt", "data/debug_15_3.txt", "data/debug_15_4.txt"] {
+            let (w, m) = parse_input(fname).unwrap();
+            for world in [w.stretch(1), w.stretch(2), w.stretch(3)] {
+                let initial = world.clone();
+                let mut sim = Simulation::new(world, m.clone());
+                sim.seek(m.len());
+                let end = sim.world.clone();
+
+                sim.seek(m.len() / 2);
+                sim.seek(0);
+                assert!(sim.world == initial, "{}", fname);
+                sim.seek(m.len());
+                assert!(sim.world == end, "{}", fname);
             }
         }
     }
 
-    return (world, moves);
+    #[cfg(feature = "tui")]
+    #[test]
+    fn undo_removes_inserted_moves() {
+        let (w, m) = parse_input("data/debug_15_2.txt").unwrap();
+        let mut sim = Simulation::new(w, m.clone());
+        sim.forward();
+        sim.insert_move(2);
+        sim.insert_move(3);
+        assert_eq!(sim.moves.len(), m.len() + 2);
+
+        sim.undo();
+        sim.undo();
+        assert_eq!(sim.moves, m);
+        sim.forward();
+        assert_eq!(sim.events.last().map(|e| e.cmd), Some(m[1]));
+    }
+
+    #[test]
+    fn examples() {
+        for (fname, normal, wide) in [("data/debug_15.txt", 10092, 9021), ("data/debug_15_2.txt", 2028, 1751)] {
+            let (w, m) = parse_input(fname).unwrap();
+            for (world, expected) in [(w.clone(), normal), (w.stretch(2), wide)] {
+                let mut sim = Simulation::new(world, m.clone());
+                sim.seek(m.len());
+                assert_eq!(sim.world.score_map(), expected, "{}", fname);
+            }
+        }
+    }
+
+    #[test]
+    fn crate_wrapped_around_robot() {
+        let (world, m) = parse_input("data/debug_15_4.txt").unwrap();
+        let mut sim = Simulation::new(world.clone(), m.clone());
+        for _ in 0..2 {
+            assert!(sim.forward().is_some_and(|e| e.outcome == Outcome::Blocked));
+        }
+        assert!(sim.world == world);
+
+        sim.seek(m.len());
+        assert_eq!(sim.world.map.iter().filter(|c| **c == '@').count(), 1);
+        for (i, id) in sim.world.owner.iter().enumerate() {
+            assert_eq!(id.is_some(), sim.world.map[i].is_ascii_uppercase(), "cell {}", i);
+        }
+        sim.seek(0);
+        assert!(sim.world == world);
+    }
 }
@@ -1,7 +1,7 @@
 use std::fs::read_to_string;
 use std::collections::BinaryHeap;
-use std::collections::VecDeque;
 use std::cmp::Ordering;
+use std::cmp::Reverse;
 
 struct Maze {
     rows: usize,
@@ -11,12 +11,156 @@ struct Maze {
     goal: usize,
 }
 
+// `cost` is negated so the max-heap pops the cheapest state first.
 #[derive(Debug)]
 struct State {
     idx: usize,
     cost: i32,
     dir: Direction,
-    act: Action,
+}
+
+// Price of each action. The puzzle charges 1 to move forward and 1000 to turn.
+#[derive(Clone, Copy, Debug)]
+struct Costs {
+    forward: i32,
+    turn: i32,
+}
+
+const PUZZLE_COSTS: Costs = Costs{forward: 1, turn: 1000};
+
+// Largest cost accepted from the command line, low enough that no path
+// through a puzzle-sized maze overflows an `i32`.
+const MAX_COST: i32 = 10_000;
+
+// The cheapest way through a maze. `on_path` marks every `(tile, direction)`
+// state lying on some optimal path, indexed like the search arrays.
+struct Solution {
+    cost: i32,
+    tiles: usize,
+    on_path: Vec<bool>,
+}
+
+// One complete route from start to goal.
+#[derive(Debug)]
+struct Path {
+    cost: i32,
+    actions: Vec<Action>,
+    tiles: Vec<usize>,
+}
+
+impl Path {
+    // The actions with repeats counted, e.g. `F3 L F12 R F2`.
+    fn describe(&self) -> String {
+        let mut out: Vec<String> = Vec::<String>::new();
+        let mut i = 0;
+        while i < self.actions.len() {
+            let a = self.actions[i];
+            let n = self.actions[i..].iter().take_while(|b| **b == a).count();
+            out.push(if n > 1 { format!("{}{}", a.symbol(), n) } else { a.symbol().to_string() });
+            i += n;
+        }
+
+        out.join(" ")
+    }
+}
+
+// Node of the tree of partial paths grown by `BestPaths`.
+struct PathNode {
+    idx: usize,
+    dir: Direction,
+    act: Option<Action>,
+    parent: Option<usize>,
+}
+
+// Partial path waiting in the `BestPaths` queue, ordered by its cost so far
+// plus the cheapest possible rest of the way, cheapest first.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Frontier {
+    bound: Reverse<i32>,
+    cost: Reverse<i32>,
+    node: usize,
+}
+
+// Yields paths from start to goal in order of cost. Each path visits a given
+// `(tile, direction)` state at most once and turns on the spot at most twice,
+// both times right to turn around, so no two paths cross the same tiles in
+// the same order. Partial paths are extended best-first with the exact cost
+// to the goal as the estimate, so only branches that can still finish within
+// the next path's cost are grown.
+struct BestPaths<'a> {
+    maze: &'a Maze,
+    costs: Costs,
+    to_goal: Vec<i32>,
+    nodes: Vec<PathNode>,
+    queue: BinaryHeap<Frontier>,
+}
+
+impl BestPaths<'_> {
+    fn on_path(&self, mut node: Option<usize>, idx: usize, dir: Direction) -> bool {
+        while let Some(n) = node {
+            if self.nodes[n].idx == idx && self.nodes[n].dir as usize == dir as usize {
+                return true;
+            }
+            node = self.nodes[n].parent;
+        }
+
+        false
+    }
+
+    // Whether turning with `act` after `node` only repeats a cheaper turn.
+    fn redundant_turn(&self, node: usize, act: Action) -> bool {
+        let last = self.nodes[node].act;
+        if act == Action::Forward || last.is_none_or(|a| a == Action::Forward) {
+            return false;
+        }
+
+        let before = self.nodes[node].parent.and_then(|p| self.nodes[p].act);
+        act != Action::TurnRight || last != Some(Action::TurnRight) || before.is_some_and(|a| a != Action::Forward)
+    }
+
+    fn finish(&self, node: usize, cost: i32) -> Path {
+        let mut actions: Vec<Action> = Vec::<Action>::new();
+        let mut tiles: Vec<usize> = Vec::<usize>::new();
+        let mut n = Some(node);
+        while let Some(i) = n {
+            if tiles.last() != Some(&self.nodes[i].idx) {
+                tiles.push(self.nodes[i].idx);
+            }
+            actions.extend(self.nodes[i].act);
+            n = self.nodes[i].parent;
+        }
+        actions.reverse();
+        tiles.reverse();
+
+        Path{cost, actions, tiles}
+    }
+}
+
+impl Iterator for BestPaths<'_> {
+    type Item = Path;
+
+    fn next(&mut self) -> Option<Path> {
+        while let Some(Frontier{cost: Reverse(cost), node, ..}) = self.queue.pop() {
+            let (idx, dir) = (self.nodes[node].idx, self.nodes[node].dir);
+            if idx == self.maze.goal {
+                return Some(self.finish(node, cost));
+            }
+
+            let here = State{idx, cost: -cost, dir};
+            for act in Action::iterator() {
+                let next = self.maze.step(&here, act, self.costs);
+                let rest = self.to_goal[4 * next.idx + (next.dir as usize)];
+                if self.maze.map[next.idx] != '.' || rest < 0 || self.redundant_turn(node, act) || self.on_path(Some(node), next.idx, next.dir) {
+                    continue;
+                }
+
+                self.nodes.push(PathNode{idx: next.idx, dir: next.dir, act: Some(act), parent: Some(node)});
+                self.queue.push(Frontier{bound: Reverse(-next.cost + rest), cost: Reverse(-next.cost), node: self.nodes.len() - 1});
+            }
+        }
+
+        None
+    }
 }
 
 #[derive(Clone,Copy,Debug)]
@@ -31,9 +175,27 @@ impl Direction {
     pub fn iterator() -> impl Iterator<Item = Self> {
         [Self::Up, Self::Right, Self::Down, Self::Left].iter().copied()
     }
+
+    fn left(self) -> Self {
+        match self {
+            Direction::Up    => Direction::Left,
+            Direction::Right => Direction::Up,
+            Direction::Down  => Direction::Right,
+            Direction::Left  => Direction::Down,
+        }
+    }
+
+    fn right(self) -> Self {
+        match self {
+            Direction::Up    => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down  => Direction::Left,
+            Direction::Left  => Direction::Up,
+        }
+    }
 }
 
-#[derive(Clone,Copy,Debug)]
+#[derive(Clone,Copy,Debug,PartialEq)]
 enum Action {
     TurnLeft = 0,
     Forward = 1,
@@ -44,6 +206,21 @@ impl Action {
     pub fn iterator() -> impl Iterator<Item = Self> {
         [Self::TurnLeft, Self::Forward, Self::TurnRight].iter().copied()
     }
+
+    fn symbol(&self) -> char {
+        match self {
+            Action::TurnLeft  => 'L',
+            Action::Forward   => 'F',
+            Action::TurnRight => 'R',
+        }
+    }
+
+    fn cost(&self, costs: Costs) -> i32 {
+        match self {
+            Action::Forward => costs.forward,
+            _ => costs.turn,
+        }
+    }
 }
 
 impl Maze {
@@ -56,152 +233,114 @@ impl Maze {
         }
     }
 
-    fn step(&self, partial: &State, act: Action) -> State {
-        match act {
-            Action::TurnLeft => {State{
-                 idx: partial.idx,
-                cost: partial.cost - 1000,
-                 dir: match partial.dir {
-                    Direction::Up    => Direction::Left,
-                    Direction::Right => Direction::Up,
-                    Direction::Down  => Direction::Right,
-                    Direction::Left  => Direction::Down,
-                },
-                 act: act
-            }},
-            Action::Forward => {State{
-                 idx: match partial.dir {
+    // The state `act` leads to from `partial`.
+    fn step(&self, partial: &State, act: Action, costs: Costs) -> State {
+        State{
+             idx: match act {
+                Action::Forward => match partial.dir {
                     Direction::Up    => partial.idx - self.cols,
                     Direction::Right => partial.idx + 1,
                     Direction::Down  => partial.idx + self.cols,
                     Direction::Left  => partial.idx - 1,
                 },
-                cost: partial.cost - 1,
-                 dir: partial.dir,
-                 act: act,
-            }},
-            Action::TurnRight => {State{
-                 idx: partial.idx,
-                cost: partial.cost - 1000,
-                 dir: match partial.dir {
-                    Direction::Up    => Direction::Right,
-                    Direction::Right => Direction::Down,
-                    Direction::Down  => Direction::Left,
-                    Direction::Left  => Direction::Up,
-                },
-                 act: act,
-            }},
+                _ => partial.idx,
+            },
+            cost: partial.cost - act.cost(costs),
+             dir: match act {
+                Action::TurnLeft  => partial.dir.left(),
+                Action::Forward   => partial.dir,
+                Action::TurnRight => partial.dir.right(),
+            },
         }
     }
     
-    fn step_inv(&self, partial: &State, act: Action) -> State {
-        match act {
-            Action::TurnLeft => {State{
-                 idx: partial.idx,
-                cost: partial.cost + 1000,
-                 dir: match partial.dir {
-                    Direction::Up    => Direction::Right,
-                    Direction::Right => Direction::Down,
-                    Direction::Down  => Direction::Left,
-                    Direction::Left  => Direction::Up,
-                },
-                 act: act,
-            }},
-            Action::Forward => {State{
-                 idx: match partial.dir {
+    // The state that `act` leads from to reach `partial`. The cost still grows,
+    // so a search running backwards from the goal orders states the same way.
+    fn step_inv(&self, partial: &State, act: Action, costs: Costs) -> State {
+        State{
+             idx: match act {
+                Action::Forward => match partial.dir {
                     Direction::Up    => partial.idx + self.cols,
                     Direction::Right => partial.idx - 1,
                     Direction::Down  => partial.idx - self.cols,
                     Direction::Left  => partial.idx + 1,
                 },
-                cost: partial.cost + 1,
-                 dir: partial.dir,
-                 act: act,
-            }},
-            Action::TurnRight => {State{
-                 idx: partial.idx,
-                cost: partial.cost + 1000,
-                 dir: match partial.dir {
-                    Direction::Up    => Direction::Left,
-                    Direction::Right => Direction::Up,
-                    Direction::Down  => Direction::Right,
-                    Direction::Left  => Direction::Down,
-                },
-                 act: act,
-            }},
+                _ => partial.idx,
+            },
+            cost: partial.cost - act.cost(costs),
+             dir: match act {
+                Action::TurnLeft  => partial.dir.right(),
+                Action::Forward   => partial.dir,
+                Action::TurnRight => partial.dir.left(),
+            },
         }
     }
 
-    fn find_path(&self) -> Result<(i32, i32), String> {
-        let mut costs: Vec<i32> = vec![-1; 4 * self.map.len()];
-        let mut acts: Vec<i32> = vec![0; 4 * self.map.len()];
-        let mut v: Vec<bool> = vec![false; 4 * self.map.len()];
-        let mut heap = BinaryHeap::<State>::new();
-        heap.push(State{idx: self.start, cost: 0, dir: Direction::Right, act: Action::Forward});
+    // Cheapest cost of every `(tile, direction)` state from any of `seeds`,
+    // or -1 where none is reachable. Runs backwards, giving the cost to reach
+    // the seeds instead, unless `forward`.
+    fn dijkstra(&self, seeds: Vec<State>, costs: Costs, forward: bool) -> Vec<i32> {
+        let mut dist: Vec<i32> = vec![-1; 4 * self.map.len()];
+        let mut heap = BinaryHeap::<State>::from(seeds);
 
         while let Some(p) = heap.pop() {
-            if !v[4 * p.idx + (p.dir as usize)] {
-                v[4 * p.idx + (p.dir as usize)] = true;
-                costs[4 * p.idx + (p.dir as usize)] = -p.cost;
-                acts[4 * p.idx + (p.dir as usize)] = 2_i32.pow(p.act as u32);
+            if dist[4 * p.idx + (p.dir as usize)] == -1 {
+                dist[4 * p.idx + (p.dir as usize)] = -p.cost;
 
                 for act in Action::iterator() {
-                    let next = self.step(&p, act);
-                    if self.map[next.idx] == '.' {
+                    let next = if forward { self.step(&p, act, costs) } else { self.step_inv(&p, act, costs) };
+                    if self.map[next.idx] == '.' && dist[4 * next.idx + (next.dir as usize)] == -1 {
                         heap.push(next);
                     }
                 }
-            } else if costs[4 * p.idx + (p.dir as usize)] == -p.cost {
-                acts[4 * p.idx + (p.dir as usize)] += 2_i32.pow(p.act as u32);
-            }
-        }
-               
-        let mut min_cost: Direction = Direction::Up;
-        for dir in Direction::iterator() {
-            if costs[4 * self.goal + (dir as usize)] != -1 {
-                if costs[4 * self.goal + (min_cost as usize)] == -1 {
-                    min_cost = dir;
-                } else if costs[4 * self.goal + (dir as usize)] < costs[4 * self.goal + (min_cost as usize)] {
-                    min_cost = dir;
-                }
             }
         }
 
-        if costs[4 * self.goal + (min_cost as usize)] > -1 {
-            let mut v: Vec<bool> = vec![false; 4 * self.map.len()];
-            let mut q: VecDeque<State> = VecDeque::<State>::new();
-            q.push_front(State{idx: self.goal, cost: 0, dir: min_cost, act: Action::Forward});
+        dist
+    }
 
-            while let Some(curr) = q.pop_back() {
-                if !v[4 * curr.idx + (curr.dir as usize)] {
-                    v[4 * curr.idx + (curr.dir as usize)] = true;
-    
-                    for act in Action::iterator() {
-                        if acts[4 * curr.idx + (curr.dir as usize)] & 2_i32.pow(act as u32) > 0 { 
-                            q.push_front(self.step_inv(&curr, act));
-                        }
-                    }
-                }
-            }
-            self.print_visited(&v);
-
-            let mut tiles: i32 = 0;
-            for r in 0..self.rows {
-                for c in 0..self.cols {
-                    for dir in Direction::iterator() {
-                        if v[4 * (r * self.cols + c) + (dir as usize)] {
-                            tiles += 1;
-                            break;
-                        }
-                    }
-                }
-            }
-            tiles -= 1;
+    fn costs_from_start(&self, costs: Costs) -> Vec<i32> {
+        self.dijkstra(vec![State{idx: self.start, cost: 0, dir: Direction::Right}], costs, true)
+    }
+
+    // Cheapest cost from every state to the goal. Arriving facing any way
+    // will do, so every goal direction starts at zero.
+    fn costs_to_goal(&self, costs: Costs) -> Vec<i32> {
+        let seeds = Direction::iterator().map(|dir| State{idx: self.goal, cost: 0, dir}).collect();
+        self.dijkstra(seeds, costs, false)
+    }
+
+    // Paths from start to goal, cheapest first. Take as many as wanted.
+    fn best_paths(&self, costs: Costs) -> BestPaths<'_> {
+        let to_goal = self.costs_to_goal(costs);
+        let mut paths = BestPaths{maze: self, costs, to_goal, nodes: Vec::new(), queue: BinaryHeap::new()};
+        let rest = paths.to_goal[4 * self.start + (Direction::Right as usize)];
+        if rest >= 0 {
+            paths.nodes.push(PathNode{idx: self.start, dir: Direction::Right, act: None, parent: None});
+            paths.queue.push(Frontier{bound: Reverse(rest), cost: Reverse(0), node: 0});
+        }
+
+        paths
+    }
 
-            return Ok((costs[4 * self.goal + (min_cost as usize)], tiles));
-        } else {
+    // A state lies on an optimal path exactly when the cheapest way to it
+    // plus the cheapest way on from it to the goal is the optimal cost, so one
+    // search from each end finds every optimal tile, whichever way the paths
+    // face on arrival.
+    fn solve(&self, costs: Costs) -> Result<Solution, String> {
+        let from_start = self.costs_from_start(costs);
+        let to_goal = self.costs_to_goal(costs);
+        let best = to_goal[4 * self.start + (Direction::Right as usize)];
+        if best < 0 {
             return Err("Failed to find path".to_string());
         }
+
+        let on_path: Vec<bool> = from_start.iter().zip(to_goal.iter())
+            .map(|(f, b)| *f >= 0 && *b >= 0 && f + b == best)
+            .collect();
+        let tiles = (0..self.map.len()).filter(|i| on_path[4 * i..4 * i + 4].iter().any(|b| *b)).count();
+
+        Ok(Solution{cost: best, tiles, on_path})
     }
 
     fn print_map(&self) {
@@ -214,7 +353,7 @@ impl Maze {
         println!("Start: {:?}, Goal: {:?}", self.start, self.goal);
     }
 
-    fn print_visited(&self, v: &Vec<bool>) {
+    fn print_visited(&self, v: &[bool]) {
         for r in 0..self.rows {
             for c in 0..self.cols {
                 match self.map[r * self.cols + c] {
@@ -258,39 +397,65 @@ impl PartialEq for State {
 
 impl Eq for State {}
 
+const USAGE: &str = "Usage: day16 [--forward=N] [--turn=N] [--paths=K] [--show]";
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
 fn main() {
-    let m = parse_input("data/debug_16.txt");
-    if let Ok((cost, tiles)) = m.find_path() {
-        println!("Debug 1: Min Distance: {:?}, Total Tiles: {:?}", cost, tiles);
-    } else {
-        println!("Failed to find path");
-    }
-    
-    let m = parse_input("data/debug_16_2.txt");
-    if let Ok((cost, tiles)) = m.find_path() {
-        println!("Debug 2: Min Distance: {:?}, Total Tiles: {:?}", cost, tiles);
-    } else {
-        println!("Failed to find path");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let known = |a: &str| a == "--show" || ["--forward=", "--turn=", "--paths="].iter().any(|f| a.starts_with(f));
+    if let Some(a) = args.iter().find(|a| !known(a)) {
+        eprintln!("Unknown argument {:?}", a);
+        usage();
     }
+    let flag = |name: &str, default: u32, max: u32| -> u32 {
+        match args.iter().find_map(|a| a.strip_prefix(name)).map(|n| n.parse::<u32>()) {
+            None => default,
+            Some(Ok(n)) if n <= max => n,
+            Some(_) => usage(),
+        }
+    };
+    let costs = Costs{
+        forward: flag("--forward=", PUZZLE_COSTS.forward as u32, MAX_COST as u32) as i32,
+        turn: flag("--turn=", PUZZLE_COSTS.turn as u32, MAX_COST as u32) as i32,
+    };
+    let k = flag("--paths=", 3, u32::MAX) as usize;
+    let show = args.iter().any(|a| a == "--show");
 
-    let m = parse_input("data/input_16.txt");
-    if let Ok((cost, tiles)) = m.find_path() {
-        println!("Input: Min Distance: {:?}, Total Tiles: {:?}", cost, tiles);
-    } else {
-        println!("Failed to find path");
+    for (name, fname) in [("Debug 1", "data/debug_16.txt"), ("Debug 2", "data/debug_16_2.txt"), ("Input", "data/input_16.txt")] {
+        let m = parse_input(fname);
+        match m.solve(costs) {
+            Ok(solution) => {
+                println!("{}: Min Distance: {:?}, Total Tiles: {:?}", name, solution.cost, solution.tiles);
+                if show {
+                    m.print_visited(&solution.on_path);
+                }
+                for (i, path) in m.best_paths(costs).take(k).enumerate() {
+                    println!("  Path {}: Cost: {:?}, Tiles: {:?}, Actions: {}", i + 1, path.cost, path.tiles.len(), path.describe());
+                }
+            }
+            Err(e) => println!("{}: {}", name, e),
+        }
     }
 }
 
 fn parse_input(fname: &str) -> Maze{
+    parse_maze(&read_to_string(fname).unwrap())
+}
+
+fn parse_maze(text: &str) -> Maze {
     let mut rows = 0;
     let mut cols = 0;
-    for line in read_to_string(fname).unwrap().lines() {
+    for line in text.lines() {
         rows += 1;
         cols = line.len();
     }
 
     let mut m: Maze = Maze::new(rows, cols);
-    for (r, line) in read_to_string(fname).unwrap().lines().enumerate() {
+    for (r, line) in text.lines().enumerate() {
         for (c, symb) in line.chars().enumerate() {
             match symb {
                 '.' | '#' => m.map[r * m.cols + c] = symb,
@@ -309,3 +474,49 @@ fn parse_input(fname: &str) -> Maze{
 
     return m;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every tile on some path the best-paths iterator finds at the optimal cost.
+    fn enumerated_tiles(m: &Maze, costs: Costs, best: i32) -> usize {
+        let mut tiles: Vec<usize> = m.best_paths(costs)
+            .take_while(|p| p.cost == best)
+            .flat_map(|p| p.tiles)
+            .collect();
+        tiles.sort();
+        tiles.dedup();
+        tiles.len()
+    }
+
+    #[test]
+    fn first_example() {
+        let m = parse_input("data/debug_16.txt");
+        let solution = m.solve(PUZZLE_COSTS).unwrap();
+        assert_eq!((solution.cost, solution.tiles), (7036, 45));
+        assert_eq!(enumerated_tiles(&m, PUZZLE_COSTS, solution.cost), 45);
+    }
+
+    #[test]
+    fn second_example() {
+        let m = parse_input("data/debug_16_2.txt");
+        let solution = m.solve(PUZZLE_COSTS).unwrap();
+        assert_eq!((solution.cost, solution.tiles), (11048, 64));
+        assert_eq!(enumerated_tiles(&m, PUZZLE_COSTS, solution.cost), 64);
+    }
+
+    // The goal is reached as cheaply from above as from below, so the
+    // cheapest routes end facing two different ways and both count.
+    #[test]
+    fn tied_goal_directions() {
+        let m = parse_maze("#######\n#.....#\n#E#.#S#\n#.....#\n#######\n");
+        let from_start = m.costs_from_start(PUZZLE_COSTS);
+        let arrivals: Vec<i32> = Direction::iterator().map(|d| from_start[4 * m.goal + d as usize]).collect();
+        assert_eq!(arrivals, vec![3006, 4006, 3006, 4006]);
+
+        let solution = m.solve(PUZZLE_COSTS).unwrap();
+        assert_eq!((solution.cost, solution.tiles), (3006, 12));
+        assert_eq!(enumerated_tiles(&m, PUZZLE_COSTS, solution.cost), 12);
+    }
+}
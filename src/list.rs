@@ -0,0 +1,329 @@
+// Arena-backed doubly-linked list. Nodes live in a `Vec` and link to each
+// other by index, so a `Handle` stays valid until its node is removed and
+// inserting or removing next to a known node is O(1). Freed slots are reused,
+// but each slot counts its removals and a handle remembers the count it was
+// issued under, so a stale handle is rejected (`get` returns `None`) even after
+// its slot holds an unrelated node.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Handle {
+    idx: usize,
+    gen: u32,
+}
+
+#[derive(Clone)]
+struct Node<T> {
+    elem: T,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+    gen: u32,
+    node: Option<Node<T>>,
+}
+
+#[derive(Clone)]
+pub struct List<T> {
+    slots: Vec<Slot<T>>,
+    vacant: Vec<usize>,
+    head: Option<usize>,
+    tail: Option<usize>,
+    len: usize,
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List { slots: Vec::new(), vacant: Vec::new(), head: None, tail: None, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn front(&self) -> Option<Handle> {
+        self.head.map(|idx| self.handle(idx))
+    }
+
+    pub fn back(&self) -> Option<Handle> {
+        self.tail.map(|idx| self.handle(idx))
+    }
+
+    pub fn get(&self, h: Handle) -> Option<&T> {
+        self.live(h).map(|node| &node.elem)
+    }
+
+    pub fn get_mut(&mut self, h: Handle) -> Option<&mut T> {
+        self.slots.get_mut(h.idx).filter(|s| s.gen == h.gen).and_then(|s| s.node.as_mut()).map(|node| &mut node.elem)
+    }
+
+    pub fn next(&self, h: Handle) -> Option<Handle> {
+        self.live(h).and_then(|node| node.next).map(|idx| self.handle(idx))
+    }
+
+    pub fn prev(&self, h: Handle) -> Option<Handle> {
+        self.live(h).and_then(|node| node.prev).map(|idx| self.handle(idx))
+    }
+
+    pub fn push_back(&mut self, elem: T) -> Handle {
+        let idx = self.alloc(elem, self.tail, None);
+        match self.tail {
+            Some(t) => self.node_mut(t).next = Some(idx),
+            None => self.head = Some(idx),
+        }
+        self.tail = Some(idx);
+
+        self.handle(idx)
+    }
+
+    pub fn push_front(&mut self, elem: T) -> Handle {
+        let idx = self.alloc(elem, None, self.head);
+        match self.head {
+            Some(h) => self.node_mut(h).prev = Some(idx),
+            None => self.tail = Some(idx),
+        }
+        self.head = Some(idx);
+
+        self.handle(idx)
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.front().and_then(|h| self.remove(h))
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.back().and_then(|h| self.remove(h))
+    }
+
+    // Inserts `elem` directly before `at`, which must be a live handle.
+    pub fn insert_before(&mut self, at: Handle, elem: T) -> Handle {
+        let prev = self.live(at).expect("stale handle").prev;
+        let idx = self.alloc(elem, prev, Some(at.idx));
+        self.node_mut(at.idx).prev = Some(idx);
+        match prev {
+            Some(p) => self.node_mut(p).next = Some(idx),
+            None => self.head = Some(idx),
+        }
+
+        self.handle(idx)
+    }
+
+    // Inserts `elem` directly after `at`, which must be a live handle.
+    pub fn insert_after(&mut self, at: Handle, elem: T) -> Handle {
+        let next = self.live(at).expect("stale handle").next;
+        let idx = self.alloc(elem, Some(at.idx), next);
+        self.node_mut(at.idx).next = Some(idx);
+        match next {
+            Some(n) => self.node_mut(n).prev = Some(idx),
+            None => self.tail = Some(idx),
+        }
+
+        self.handle(idx)
+    }
+
+    pub fn remove(&mut self, h: Handle) -> Option<T> {
+        let slot = self.slots.get_mut(h.idx).filter(|s| s.gen == h.gen)?;
+        let node = slot.node.take()?;
+        slot.gen = slot.gen.wrapping_add(1);
+        match node.prev {
+            Some(p) => self.node_mut(p).next = node.next,
+            None => self.head = node.next,
+        }
+        match node.next {
+            Some(n) => self.node_mut(n).prev = node.prev,
+            None => self.tail = node.prev,
+        }
+        self.vacant.push(h.idx);
+        self.len -= 1;
+
+        Some(node.elem)
+    }
+
+    pub fn handles(&self) -> Handles<'_, T> {
+        Handles { list: self, next: self.head }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.handles().map(|h| &self.node(h.idx).unwrap().elem)
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        let elems = self.slots.iter_mut()
+            .map(|s| s.node.as_mut().map(|Node { elem, next, .. }| (elem, *next)))
+            .collect();
+        IterMut { elems, next: self.head }
+    }
+
+    fn alloc(&mut self, elem: T, prev: Option<usize>, next: Option<usize>) -> usize {
+        let node = Some(Node { elem, prev, next });
+        self.len += 1;
+        match self.vacant.pop() {
+            Some(idx) => {
+                self.slots[idx].node = node;
+                idx
+            }
+            None => {
+                self.slots.push(Slot { gen: 0, node });
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    fn handle(&self, idx: usize) -> Handle {
+        Handle { idx, gen: self.slots[idx].gen }
+    }
+
+    fn live(&self, h: Handle) -> Option<&Node<T>> {
+        self.slots.get(h.idx).filter(|s| s.gen == h.gen).and_then(|s| s.node.as_ref())
+    }
+
+    fn node(&self, idx: usize) -> Option<&Node<T>> {
+        self.slots.get(idx).and_then(|s| s.node.as_ref())
+    }
+
+    fn node_mut(&mut self, idx: usize) -> &mut Node<T> {
+        self.slots[idx].node.as_mut().unwrap()
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        for elem in iter {
+            list.push_back(elem);
+        }
+        list
+    }
+}
+
+pub struct Handles<'a, T> {
+    list: &'a List<T>,
+    next: Option<usize>,
+}
+
+impl<T> Iterator for Handles<'_, T> {
+    type Item = Handle;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|idx| {
+            self.next = self.list.node(idx).unwrap().next;
+            self.list.handle(idx)
+        })
+    }
+}
+
+// Walks the links like `Handles` but hands out the elements mutably. Each
+// live slot's element is borrowed up front and taken when the walk reaches it.
+pub struct IterMut<'a, T> {
+    elems: Vec<Option<(&'a mut T, Option<usize>)>>,
+    next: Option<usize>,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (elem, next) = self.elems[self.next?].take()?;
+        self.next = next;
+        Some(elem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(list: &List<i32>) -> Vec<i32> {
+        list.iter().copied().collect()
+    }
+
+    #[test]
+    fn insert_before_and_after() {
+        let mut list: List<i32> = List::new();
+        let b = list.push_back(2);
+        let a = list.insert_before(b, 1);
+        let d = list.insert_after(b, 4);
+        list.insert_before(d, 3);
+        list.insert_after(d, 5);
+        list.insert_before(a, 0);
+        assert_eq!(items(&list), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(list.len(), 6);
+        assert_eq!(list.get(list.front().unwrap()), Some(&0));
+        assert_eq!(list.get(list.back().unwrap()), Some(&5));
+        assert_eq!(list.next(b).and_then(|h| list.get(h)), Some(&3));
+        assert_eq!(list.prev(b), Some(a));
+    }
+
+    #[test]
+    fn remove_relinks_neighbours() {
+        let mut list: List<i32> = (0..5).collect();
+        let hs: Vec<Handle> = list.handles().collect();
+        assert_eq!(list.remove(hs[2]), Some(2));
+        assert_eq!(list.next(hs[1]), Some(hs[3]));
+        assert_eq!(list.prev(hs[3]), Some(hs[1]));
+        assert_eq!(list.remove(hs[0]), Some(0));
+        assert_eq!(list.remove(hs[4]), Some(4));
+        assert_eq!(list.front(), Some(hs[1]));
+        assert_eq!(list.back(), Some(hs[3]));
+        assert_eq!(items(&list), vec![1, 3]);
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_back(), Some(3));
+        assert!(list.is_empty());
+        assert_eq!((list.front(), list.back()), (None, None));
+    }
+
+    #[test]
+    fn freed_slots_are_reused() {
+        let mut list: List<i32> = (0..3).collect();
+        let hs: Vec<Handle> = list.handles().collect();
+        list.remove(hs[1]);
+        let h = list.insert_after(hs[0], 10);
+        assert_eq!(h.idx, hs[1].idx);
+        assert_eq!(list.slots.len(), 3);
+        assert_eq!(items(&list), vec![0, 10, 2]);
+        assert_eq!(list.get(h), Some(&10));
+    }
+
+    #[test]
+    fn stale_handles_are_rejected() {
+        let mut list: List<i32> = (0..3).collect();
+        let stale = list.handles().nth(1).unwrap();
+        list.remove(stale);
+        assert_eq!(list.get(stale), None);
+        assert_eq!(list.remove(stale), None);
+
+        // The slot now holds an unrelated node; the old handle must not see it.
+        let fresh = list.push_back(7);
+        assert_eq!(fresh.idx, stale.idx);
+        assert_ne!(fresh, stale);
+        assert_eq!(list.get(stale), None);
+        assert_eq!(list.get_mut(stale), None);
+        assert_eq!(list.next(stale), None);
+        assert_eq!(list.remove(stale), None);
+        assert_eq!(items(&list), vec![0, 2, 7]);
+    }
+
+    #[test]
+    fn iter_mut_follows_links() {
+        let mut list: List<i32> = List::new();
+        let b = list.push_back(2);
+        list.push_front(1);
+        list.insert_after(b, 3);
+        for v in list.iter_mut() {
+            *v *= 10;
+        }
+        assert_eq!(items(&list), vec![10, 20, 30]);
+        assert_eq!(list.iter_mut().count(), 3);
+    }
+}
@@ -0,0 +1,681 @@
+// Dense row-major matrices over any `Scalar`. Determinant and rank use
+// fraction-free (Bareiss) elimination, so they are exact for integers as well
+// as fields. Inverse, LU and `solve` need a `Field`; integer matrices can be
+// lifted to `Rational` for those.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+pub trait Scalar:
+    Copy + PartialEq + fmt::Debug
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self> + Neg<Output = Self>
+{
+    fn zero() -> Self;
+    fn one() -> Self;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::zero()
+    }
+
+    // Size used to pick pivots; only matters for inexact types.
+    fn magnitude(&self) -> f64;
+}
+
+// Scalars where division is exact.
+pub trait Field: Scalar {}
+
+macro_rules! int_scalar {
+    ($($t:ty),*) => {$(
+        impl Scalar for $t {
+            fn zero() -> Self { 0 }
+            fn one() -> Self { 1 }
+            fn magnitude(&self) -> f64 { self.abs() as f64 }
+        }
+    )*};
+}
+
+int_scalar!(i32, i64, i128);
+
+// Pivots smaller than this are treated as zero.
+const EPSILON: f64 = 1e-9;
+
+impl Scalar for f64 {
+    fn zero() -> Self { 0.0 }
+    fn one() -> Self { 1.0 }
+    fn is_zero(&self) -> bool { self.abs() < EPSILON }
+    fn magnitude(&self) -> f64 { self.abs() }
+}
+
+impl Field for f64 {}
+
+// Exact fraction kept in lowest terms with a positive denominator.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    pub fn new(num: i128, den: i128) -> Self {
+        assert!(den != 0, "zero denominator");
+        let g = gcd(num, den).max(1) * den.signum();
+        Rational { num: checked(num.checked_div(g)), den: checked(den.checked_div(g)) }
+    }
+
+    pub fn num(&self) -> i128 {
+        self.num
+    }
+
+    pub fn den(&self) -> i128 {
+        self.den
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den == 1
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 { checked(a.checked_abs()) } else { gcd(b, checked(a.checked_rem(b))) }
+}
+
+impl From<i64> for Rational {
+    fn from(v: i64) -> Self {
+        Rational { num: v as i128, den: 1 }
+    }
+}
+
+impl fmt::Debug for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        checked(self.num.checked_mul(other.den)).cmp(&checked(other.num.checked_mul(self.den)))
+    }
+}
+
+// Numerators and denominators are kept in i128 and every operation is
+// checked: a result that does not fit panics with "Rational overflow" rather
+// than wrapping. Operands are cross-reduced first, so only genuinely large
+// fractions (terms beyond roughly 2^63 on each side) can hit the limit.
+fn checked(v: Option<i128>) -> i128 {
+    v.expect("Rational overflow")
+}
+
+impl Add for Rational {
+    type Output = Rational;
+    fn add(self, rhs: Rational) -> Rational {
+        let g = gcd(self.den, rhs.den);
+        let (l, r) = (self.den / g, rhs.den / g);
+        let num = checked(checked(self.num.checked_mul(r)).checked_add(checked(rhs.num.checked_mul(l))));
+        Rational::new(num, checked(self.den.checked_mul(r)))
+    }
+}
+
+impl Sub for Rational {
+    type Output = Rational;
+    fn sub(self, rhs: Rational) -> Rational {
+        let g = gcd(self.den, rhs.den);
+        let (l, r) = (self.den / g, rhs.den / g);
+        let num = checked(checked(self.num.checked_mul(r)).checked_sub(checked(rhs.num.checked_mul(l))));
+        Rational::new(num, checked(self.den.checked_mul(r)))
+    }
+}
+
+impl Mul for Rational {
+    type Output = Rational;
+    fn mul(self, rhs: Rational) -> Rational {
+        let g1 = gcd(self.num, rhs.den).max(1);
+        let g2 = gcd(rhs.num, self.den).max(1);
+        Rational::new(
+            checked((self.num / g1).checked_mul(rhs.num / g2)),
+            checked((self.den / g2).checked_mul(rhs.den / g1)),
+        )
+    }
+}
+
+impl Div for Rational {
+    type Output = Rational;
+    fn div(self, rhs: Rational) -> Rational {
+        let g1 = gcd(self.num, rhs.num).max(1);
+        let g2 = gcd(rhs.den, self.den).max(1);
+        Rational::new(
+            checked((self.num / g1).checked_mul(rhs.den / g2)),
+            checked((self.den / g2).checked_mul(rhs.num / g1)),
+        )
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        Rational { num: checked(self.num.checked_neg()), den: self.den }
+    }
+}
+
+impl Scalar for Rational {
+    fn zero() -> Self { Rational { num: 0, den: 1 } }
+    fn one() -> Self { Rational { num: 1, den: 1 } }
+    fn magnitude(&self) -> f64 { (self.num as f64 / self.den as f64).abs() }
+}
+
+impl Field for Rational {}
+
+#[derive(Clone, PartialEq)]
+pub struct Matrix<T> {
+    pub rows: usize,
+    pub cols: usize,
+    data: Vec<T>,
+}
+
+// Outcome of solving `A x = b`. An underdetermined system reports one
+// particular solution (free variables set to zero) and the free columns.
+#[derive(Debug, PartialEq)]
+pub enum Solution<T> {
+    Unique(Vec<T>),
+    Underdetermined { particular: Vec<T>, free: Vec<usize> },
+    Inconsistent,
+}
+
+// `P A = L U` with unit-diagonal `L` and `U` packed into one matrix, and
+// `perm[i]` the row of `A` that ended up in row `i`.
+pub struct Lu<T> {
+    pub lu: Matrix<T>,
+    pub perm: Vec<usize>,
+    sign: T,
+}
+
+impl<T: Scalar> Matrix<T> {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        Matrix { rows, cols, data: vec![T::zero(); rows * cols] }
+    }
+
+    pub fn identity(n: usize) -> Self {
+        let mut m = Self::new(n, n);
+        for i in 0..n {
+            m.data[i * n + i] = T::one();
+        }
+        m
+    }
+
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Self {
+        let cols = rows.first().map_or(0, |r| r.len());
+        assert!(rows.iter().all(|r| r.len() == cols), "ragged rows");
+        Matrix { rows: rows.len(), cols, data: rows.into_iter().flatten().collect() }
+    }
+
+    pub fn get(&self, r: usize, c: usize) -> Option<&T> {
+        if self.in_bounds(r, c) {
+            Some(&self.data[r * self.cols + c])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, r: usize, c: usize) -> Option<&mut T> {
+        if self.in_bounds(r, c) {
+            Some(&mut self.data[r * self.cols + c])
+        } else {
+            None
+        }
+    }
+
+    pub fn in_bounds(&self, r: usize, c: usize) -> bool {
+        r < self.rows && c < self.cols
+    }
+
+    pub fn row(&self, r: usize) -> &[T] {
+        &self.data[r * self.cols..(r + 1) * self.cols]
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = Self::new(self.cols, self.rows);
+        for r in 0..self.rows {
+            for c in 0..self.cols {
+                t.data[c * self.rows + r] = self.data[r * self.cols + c];
+            }
+        }
+        t
+    }
+
+    pub fn map<U: Scalar>(&self, f: impl Fn(T) -> U) -> Matrix<U> {
+        Matrix { rows: self.rows, cols: self.cols, data: self.data.iter().map(|v| f(*v)).collect() }
+    }
+
+    // Submatrix made of the given rows and columns, in that order.
+    pub fn select(&self, rows: &[usize], cols: &[usize]) -> Self {
+        Matrix::from_rows(rows.iter().map(|r| cols.iter().map(|c| self.data[r * self.cols + c]).collect()).collect())
+    }
+
+    // Copy with column `c` replaced by `v`, as used by Cramer's rule.
+    pub fn with_col(&self, c: usize, v: &[T]) -> Self {
+        assert!(v.len() == self.rows);
+        let mut m = self.clone();
+        for (r, x) in v.iter().enumerate() {
+            m.data[r * self.cols + c] = *x;
+        }
+        m
+    }
+
+    // Fraction-free row echelon form. Returns the reduced copy, the pivot
+    // columns and the sign picked up from row swaps. Every division is exact
+    // for integers because each entry is a minor of the original matrix.
+    fn bareiss(&self) -> (Self, Vec<usize>, T) {
+        let mut a = self.clone();
+        let mut pivots: Vec<usize> = Vec::new();
+        let mut sign = T::one();
+        let mut prev = T::one();
+        let mut r = 0;
+        for c in 0..a.cols {
+            if r == a.rows {
+                break;
+            }
+            let Some(p) = (r..a.rows)
+                .filter(|i| !a.data[i * a.cols + c].is_zero())
+                .max_by(|i, j| a.data[i * a.cols + c].magnitude().total_cmp(&a.data[j * a.cols + c].magnitude()))
+            else {
+                continue;
+            };
+            if p != r {
+                a.swap_rows(p, r);
+                sign = -sign;
+            }
+
+            let pivot = a.data[r * a.cols + c];
+            for i in (r + 1)..a.rows {
+                let lead = a.data[i * a.cols + c];
+                for j in (c + 1)..a.cols {
+                    a.data[i * a.cols + j] = (pivot * a.data[i * a.cols + j] - lead * a.data[r * a.cols + j]) / prev;
+                }
+                a.data[i * a.cols + c] = T::zero();
+            }
+            prev = pivot;
+            pivots.push(c);
+            r += 1;
+        }
+
+        (a, pivots, sign)
+    }
+
+    pub fn rank(&self) -> usize {
+        self.bareiss().1.len()
+    }
+
+    pub fn det(&self) -> T {
+        assert!(self.rows == self.cols, "determinant of a non-square matrix");
+        if self.rows == 0 {
+            return T::one();
+        }
+
+        let (a, pivots, sign) = self.bareiss();
+        if pivots.len() < self.rows {
+            return T::zero();
+        }
+        sign * a.data[self.rows * self.cols - 1]
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        for c in 0..self.cols {
+            self.data.swap(a * self.cols + c, b * self.cols + c);
+        }
+    }
+}
+
+impl Matrix<i64> {
+    pub fn to_rational(&self) -> Matrix<Rational> {
+        self.map(Rational::from)
+    }
+}
+
+impl<T: Field> Matrix<T> {
+    // LU decomposition with partial pivoting, or `None` if singular.
+    pub fn lu(&self) -> Option<Lu<T>> {
+        assert!(self.rows == self.cols, "LU of a non-square matrix");
+        let n = self.rows;
+        let mut lu = self.clone();
+        let mut perm: Vec<usize> = (0..n).collect();
+        let mut sign = T::one();
+
+        for k in 0..n {
+            let p = (k..n).max_by(|i, j| lu.data[i * n + k].magnitude().total_cmp(&lu.data[j * n + k].magnitude()))?;
+            if lu.data[p * n + k].is_zero() {
+                return None;
+            }
+            if p != k {
+                lu.swap_rows(p, k);
+                perm.swap(p, k);
+                sign = -sign;
+            }
+
+            for i in (k + 1)..n {
+                let f = lu.data[i * n + k] / lu.data[k * n + k];
+                lu.data[i * n + k] = f;
+                for j in (k + 1)..n {
+                    lu.data[i * n + j] = lu.data[i * n + j] - f * lu.data[k * n + j];
+                }
+            }
+        }
+
+        Some(Lu { lu, perm, sign })
+    }
+
+    pub fn inverse(&self) -> Option<Self> {
+        let lu = self.lu()?;
+        let n = self.rows;
+        let mut inv = Self::new(n, n);
+        for c in 0..n {
+            let e: Vec<T> = (0..n).map(|r| if r == c { T::one() } else { T::zero() }).collect();
+            for (r, v) in lu.solve(&e).into_iter().enumerate() {
+                inv.data[r * n + c] = v;
+            }
+        }
+        Some(inv)
+    }
+
+    // Solves `A x = b` by Gauss-Jordan elimination on the augmented matrix.
+    pub fn solve(&self, b: &[T]) -> Solution<T> {
+        assert!(b.len() == self.rows);
+        let mut a = Self::new(self.rows, self.cols + 1);
+        for (r, v) in b.iter().enumerate() {
+            for c in 0..self.cols {
+                a.data[r * a.cols + c] = self.data[r * self.cols + c];
+            }
+            a.data[r * a.cols + self.cols] = *v;
+        }
+
+        let mut pivots: Vec<usize> = Vec::new();
+        let mut r = 0;
+        for c in 0..self.cols {
+            if r == a.rows {
+                break;
+            }
+            let Some(p) = (r..a.rows)
+                .filter(|i| !a.data[i * a.cols + c].is_zero())
+                .max_by(|i, j| a.data[i * a.cols + c].magnitude().total_cmp(&a.data[j * a.cols + c].magnitude()))
+            else {
+                continue;
+            };
+            a.swap_rows(p, r);
+
+            let pivot = a.data[r * a.cols + c];
+            for j in c..a.cols {
+                a.data[r * a.cols + j] = a.data[r * a.cols + j] / pivot;
+            }
+            for i in 0..a.rows {
+                let f = a.data[i * a.cols + c];
+                if i != r && !f.is_zero() {
+                    for j in c..a.cols {
+                        a.data[i * a.cols + j] = a.data[i * a.cols + j] - f * a.data[r * a.cols + j];
+                    }
+                }
+            }
+            pivots.push(c);
+            r += 1;
+        }
+
+        if (r..a.rows).any(|i| !a.data[i * a.cols + self.cols].is_zero()) {
+            return Solution::Inconsistent;
+        }
+
+        let mut x = vec![T::zero(); self.cols];
+        for (i, c) in pivots.iter().enumerate() {
+            x[*c] = a.data[i * a.cols + self.cols];
+        }
+
+        if pivots.len() == self.cols {
+            Solution::Unique(x)
+        } else {
+            let free = (0..self.cols).filter(|c| !pivots.contains(c)).collect();
+            Solution::Underdetermined { particular: x, free }
+        }
+    }
+}
+
+impl<T: Field> Lu<T> {
+    pub fn det(&self) -> T {
+        (0..self.lu.rows).fold(self.sign, |d, i| d * self.lu.data[i * self.lu.cols + i])
+    }
+
+    pub fn solve(&self, b: &[T]) -> Vec<T> {
+        let n = self.lu.rows;
+        let mut y: Vec<T> = self.perm.iter().map(|p| b[*p]).collect();
+        for i in 0..n {
+            for j in 0..i {
+                y[i] = y[i] - self.lu.data[i * n + j] * y[j];
+            }
+        }
+        for i in (0..n).rev() {
+            for j in (i + 1)..n {
+                y[i] = y[i] - self.lu.data[i * n + j] * y[j];
+            }
+            y[i] = y[i] / self.lu.data[i * n + i];
+        }
+        y
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Matrix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for r in 0..self.rows {
+            writeln!(f, "{:?}", &self.data[r * self.cols..(r + 1) * self.cols])?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Scalar> Mul<&Matrix<T>> for &Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn mul(self, rhs: &Matrix<T>) -> Matrix<T> {
+        assert!(self.cols == rhs.rows);
+        let mut res = Matrix::new(self.rows, rhs.cols);
+        for i in 0..self.rows {
+            for k in 0..self.cols {
+                let a = self.data[i * self.cols + k];
+                for j in 0..rhs.cols {
+                    res.data[i * rhs.cols + j] = res.data[i * rhs.cols + j] + a * rhs.data[k * rhs.cols + j];
+                }
+            }
+        }
+        res
+    }
+}
+
+impl<T: Scalar> Mul<Matrix<T>> for Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn mul(self, rhs: Matrix<T>) -> Matrix<T> {
+        &self * &rhs
+    }
+}
+
+impl<T: Scalar> Mul<&Matrix<T>> for Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn mul(self, rhs: &Matrix<T>) -> Matrix<T> {
+        &self * rhs
+    }
+}
+
+impl<T: Scalar> Add<&Matrix<T>> for &Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn add(self, rhs: &Matrix<T>) -> Matrix<T> {
+        assert!(self.rows == rhs.rows && self.cols == rhs.cols);
+        Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self.data.iter().zip(&rhs.data).map(|(a, b)| *a + *b).collect(),
+        }
+    }
+}
+
+impl<T: Scalar> Add<Matrix<T>> for Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn add(self, rhs: Matrix<T>) -> Matrix<T> {
+        &self + &rhs
+    }
+}
+
+impl<T: Scalar> Add<&Matrix<T>> for Matrix<T> {
+    type Output = Matrix<T>;
+
+    fn add(self, rhs: &Matrix<T>) -> Matrix<T> {
+        &self + rhs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(rows: &[&[i64]]) -> Matrix<i64> {
+        Matrix::from_rows(rows.iter().map(|r| r.to_vec()).collect())
+    }
+
+    fn ratio(n: i128, d: i128) -> Rational {
+        Rational::new(n, d)
+    }
+
+    fn close(a: &Matrix<f64>, b: &Matrix<f64>) -> bool {
+        a.rows == b.rows && a.cols == b.cols && a.data.iter().zip(&b.data).all(|(x, y)| (x - y).abs() < 1e-9)
+    }
+
+    #[test]
+    fn rational_normalises() {
+        let r = ratio(6, -4);
+        assert_eq!((r.num(), r.den()), (-3, 2));
+        assert_eq!(ratio(0, -7), Rational::zero());
+        assert_eq!(ratio(1, 2) + ratio(1, 3), ratio(5, 6));
+        assert_eq!(ratio(1, 2) - ratio(1, 3), ratio(1, 6));
+        assert_eq!(ratio(2, 3) * ratio(9, 4), ratio(3, 2));
+        assert_eq!(ratio(2, 3) / ratio(-4, 9), ratio(-3, 2));
+        assert!(ratio(1, 3) < ratio(1, 2));
+        assert!(ratio(4, 2).is_integer());
+    }
+
+    #[test]
+    fn rational_cross_reduces_before_multiplying() {
+        let big = i128::MAX / 3;
+        assert_eq!(ratio(big, 7) * ratio(7, big), Rational::one());
+        assert_eq!(ratio(1, big) + ratio(1, big), ratio(2, big));
+    }
+
+    #[test]
+    #[should_panic(expected = "Rational overflow")]
+    fn rational_overflow_panics() {
+        let _ = ratio(i128::MAX, 1) + ratio(1, 1);
+    }
+
+    #[test]
+    fn det_and_rank_over_integers() {
+        let a = int(&[&[2, -3, 1], &[2, 0, -1], &[1, 4, 5]]);
+        assert_eq!(a.det(), 49);
+        assert_eq!(a.rank(), 3);
+        assert_eq!(a.map(|v| v as i128).det(), 49);
+        assert_eq!(a.map(|v| v as i32).det(), 49);
+
+        let singular = int(&[&[1, 2, 3], &[2, 4, 6], &[1, 0, 1]]);
+        assert_eq!(singular.det(), 0);
+        assert_eq!(singular.rank(), 2);
+
+        let wide = int(&[&[1, 2, 3, 4], &[2, 4, 6, 8]]);
+        assert_eq!(wide.rank(), 1);
+        assert_eq!(Matrix::<i64>::new(0, 0).det(), 1);
+        assert_eq!(Matrix::<i64>::identity(4).det(), 1);
+    }
+
+    #[test]
+    fn det_needs_a_row_swap() {
+        let a = int(&[&[0, 1], &[1, 0]]);
+        assert_eq!(a.det(), -1);
+        assert_eq!(a.to_rational().det(), ratio(-1, 1));
+    }
+
+    #[test]
+    fn det_over_f64_and_rational() {
+        let a = int(&[&[2, -3, 1], &[2, 0, -1], &[1, 4, 5]]);
+        assert!((a.map(|v| v as f64).det() - 49.0).abs() < 1e-9);
+        assert_eq!(a.to_rational().det(), ratio(49, 1));
+        assert_eq!(a.to_rational().rank(), 3);
+    }
+
+    #[test]
+    fn lu_matches_det_and_solves() {
+        let a = int(&[&[0, 2, 1], &[1, 1, 1], &[4, 0, -2]]).to_rational();
+        let lu = a.lu().unwrap();
+        assert_eq!(lu.det(), a.det());
+        let b: Vec<Rational> = [5, 6, 2].iter().map(|v| Rational::from(*v)).collect();
+        let x = lu.solve(&b);
+        assert_eq!(x, vec![Rational::from(2), Rational::from(1), Rational::from(3)]);
+
+        let f = a.map(|v| v.num() as f64);
+        let fx = f.lu().unwrap().solve(&[5.0, 6.0, 2.0]);
+        assert!(fx.iter().zip([2.0, 1.0, 3.0]).all(|(x, y)| (x - y).abs() < 1e-9));
+
+        assert!(int(&[&[1, 2], &[2, 4]]).to_rational().lu().is_none());
+    }
+
+    #[test]
+    fn inverse_round_trips() {
+        let a = int(&[&[4, 7, 2], &[3, 6, 1], &[2, 5, 3]]).to_rational();
+        let inv = a.inverse().unwrap();
+        assert_eq!(&a * &inv, Matrix::identity(3));
+        assert_eq!(&inv * &a, Matrix::identity(3));
+
+        let f = a.map(|v| v.num() as f64);
+        assert!(close(&(&f * &f.inverse().unwrap()), &Matrix::identity(3)));
+
+        assert!(int(&[&[1, 2], &[2, 4]]).to_rational().inverse().is_none());
+    }
+
+    #[test]
+    fn solve_unique() {
+        let a = int(&[&[94, 22], &[34, 67]]).to_rational();
+        let b = [Rational::from(8400), Rational::from(5400)];
+        assert_eq!(a.solve(&b), Solution::Unique(vec![Rational::from(80), Rational::from(40)]));
+
+        let f = a.map(|v| v.num() as f64);
+        let Solution::Unique(x) = f.solve(&[8400.0, 5400.0]) else { panic!("expected a unique solution") };
+        assert!((x[0] - 80.0).abs() < 1e-9 && (x[1] - 40.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn solve_underdetermined() {
+        let a = int(&[&[1, 2, 3], &[2, 4, 7]]).to_rational();
+        let b = [Rational::from(6), Rational::from(13)];
+        let Solution::Underdetermined { particular, free } = a.solve(&b) else {
+            panic!("expected free variables");
+        };
+        assert_eq!(free, vec![1]);
+        assert_eq!(particular, vec![Rational::from(3), Rational::zero(), Rational::from(1)]);
+
+        let f = a.map(|v| v.num() as f64);
+        let Solution::Underdetermined { free, .. } = f.solve(&[6.0, 13.0]) else { panic!("expected free variables") };
+        assert_eq!(free, vec![1]);
+    }
+
+    #[test]
+    fn solve_inconsistent() {
+        let a = int(&[&[1, 2], &[2, 4]]).to_rational();
+        assert_eq!(a.solve(&[Rational::from(3), Rational::from(7)]), Solution::Inconsistent);
+
+        let f = a.map(|v| v.num() as f64);
+        assert_eq!(f.solve(&[3.0, 7.0]), Solution::Inconsistent);
+    }
+}